@@ -5,4 +5,5 @@ fn main() {
         .atleast_version("1.4.99.1")
         .probe("x11")
         .unwrap();
+    pkg_config::Config::new().probe("x11-xcb").unwrap();
 }
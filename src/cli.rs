@@ -0,0 +1,280 @@
+/*
+ * This file is part of moemenu.
+ * Copyright (C) 2021 fence.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::error::Error;
+use std::fmt;
+
+use rgb::RGBA8;
+
+use crate::config::{self, CaseMode, Config, Dimension, EngineKind, Monitor, Position};
+
+pub const USAGE: &str =
+    "usage: moemenu [-bciSv] [-l lines] [-g columns] [-p prompt] [-fn font] [-m monitor]
+                [-x xoffset] [-y yoffset] [-z width] [-nb color] [-nf color]
+                [-sb color] [-sf color] [-w windowid] [-e engine]";
+
+/// What main should do after the arguments have been parsed
+pub enum Command {
+    Run,
+    PrintVersion,
+}
+
+#[derive(Debug)]
+pub struct UsageError {
+    details: String,
+}
+
+impl UsageError {
+    fn new(msg: &str) -> Self {
+        UsageError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for UsageError {}
+
+/// Parses dmenu style arguments and applies them on top of the config.
+pub fn parse_args<I>(args: I, config: &mut Config) -> Result<Command, UsageError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-v" => return Ok(Command::PrintVersion),
+            "-b" => config.position = Position::Bottom,
+            "-c" => config.position = Position::Center,
+            "-i" => config.case = CaseMode::Insensitive,
+            "-S" => config.case = CaseMode::Smart,
+            flag @ ("-l" | "-g" | "-p" | "-fn" | "-m" | "-x" | "-y" | "-z" | "-nb" | "-nf"
+            | "-sb" | "-sf" | "-w" | "-e") => {
+                let value = match args.next() {
+                    Some(value) => value,
+                    None => return Err(UsageError::new(&format!("{} requires an argument", flag))),
+                };
+                apply_option(flag, value, config)?;
+            }
+            _ => return Err(UsageError::new(&format!("unknown option: {}", arg))),
+        }
+    }
+
+    Ok(Command::Run)
+}
+
+fn apply_option(flag: &str, value: String, config: &mut Config) -> Result<(), UsageError> {
+    match flag {
+        "-fn" => apply_font(&value, config),
+        "-e" => {
+            config.search_engine = EngineKind::from_name(&value)
+                .ok_or_else(|| UsageError::new(&format!("{}: unknown engine: {}", flag, value)))?
+        }
+        "-m" => config.monitor = Monitor::from_name(&value),
+        "-nb" => config.colors.background = parse_color(flag, &value)?,
+        "-nf" => config.colors.font = parse_color(flag, &value)?,
+        "-sb" => config.colors.selected_background = parse_color(flag, &value)?,
+        "-sf" => config.colors.selected_font = parse_color(flag, &value)?,
+        "-l" => config.lines = parse_number(flag, &value)?,
+        "-g" => config.columns = parse_number(flag, &value)?,
+        "-x" => config.x = parse_dimension(flag, &value)?,
+        "-y" => config.y = parse_dimension(flag, &value)?,
+        "-z" => config.width = Some(parse_dimension(flag, &value)?),
+        "-w" => config.embed = Some(parse_window_id(flag, &value)?),
+        "-p" => config.prompt = Some(value),
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Takes a Pango font description like "Iosevka 11" or the "family:size=13" form dmenu uses.
+pub fn apply_font(value: &str, config: &mut Config) {
+    let mut parts = value.split(':');
+    let mut font = parts.next().unwrap_or_default().to_string();
+
+    for part in parts {
+        // size is in points like in a Pango description, pixelsize in pixels like font_size
+        if let Some(Ok(size)) = part.strip_prefix("size=").map(|s| s.parse::<f64>()) {
+            font = format!("{} {}", font, size);
+        } else if let Some(Ok(size)) = part.strip_prefix("pixelsize=").map(|s| s.parse::<f64>()) {
+            config.font_size = size;
+        }
+    }
+
+    if !font.trim().is_empty() {
+        config.font = Some(font);
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, UsageError> {
+    value
+        .parse::<T>()
+        .map_err(|_| UsageError::new(&format!("{}: invalid number: {}", flag, value)))
+}
+
+fn parse_dimension(flag: &str, value: &str) -> Result<Dimension, UsageError> {
+    Dimension::parse(value)
+        .ok_or_else(|| UsageError::new(&format!("{}: invalid size: {}", flag, value)))
+}
+
+fn parse_window_id(flag: &str, value: &str) -> Result<u32, UsageError> {
+    // window ids are usually handed around in hex
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse::<u32>().ok(),
+    };
+
+    parsed.ok_or_else(|| UsageError::new(&format!("{}: invalid window id: {}", flag, value)))
+}
+
+/// Takes the "#rgb" and "#rrggbb" colors dmenu accepts and the alpha variants of them.
+fn parse_color(flag: &str, value: &str) -> Result<RGBA8, UsageError> {
+    config::parse_color(value)
+        .ok_or_else(|| UsageError::new(&format!("{}: invalid color: {}", flag, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::DEFAULT_CONFIG;
+
+    fn parse(args: &[&str]) -> (Result<Command, UsageError>, Config) {
+        let mut config = DEFAULT_CONFIG;
+        let result = parse_args(args.iter().map(|s| s.to_string()), &mut config);
+        (result, config)
+    }
+
+    #[test]
+    fn applies_switches() {
+        let (result, config) = parse(&["-b", "-i"]);
+        assert!(matches!(result, Ok(Command::Run)));
+        assert!(matches!(config.position, Position::Bottom));
+        assert!(matches!(config.case, CaseMode::Insensitive));
+
+        let (_, config) = parse(&["-c", "-S"]);
+        assert!(matches!(config.position, Position::Center));
+        assert!(matches!(config.case, CaseMode::Smart));
+    }
+
+    #[test]
+    fn applies_options_with_values() {
+        let (result, config) = parse(&[
+            "-l",
+            "10",
+            "-g",
+            "3",
+            "-p",
+            "run:",
+            "-m",
+            "DP-1",
+            "-x",
+            "10",
+            "-y",
+            "5%",
+            "-z",
+            "50%",
+            "-nb",
+            "#222",
+            "-nf",
+            "#bbbbbb",
+            "-sb",
+            "#00557780",
+            "-sf",
+            "#eee",
+            "-w",
+            "0x1a00003",
+            "-e",
+            "fuzzy",
+        ]);
+        assert!(matches!(result, Ok(Command::Run)));
+        assert_eq!(config.lines, 10);
+        assert_eq!(config.columns, 3);
+        assert_eq!(config.prompt.as_deref(), Some("run:"));
+        assert_eq!(config.monitor, Monitor::Name("DP-1".to_string()));
+        assert_eq!(config.x, Dimension::Pixels(10));
+        assert_eq!(config.y, Dimension::Percent(5.0));
+        assert_eq!(config.width, Some(Dimension::Percent(50.0)));
+        assert_eq!(config.colors.background, RGBA8::new(34, 34, 34, 255));
+        assert_eq!(config.colors.font, RGBA8::new(187, 187, 187, 255));
+        assert_eq!(
+            config.colors.selected_background,
+            RGBA8::new(0, 85, 119, 128)
+        );
+        assert_eq!(config.colors.selected_font, RGBA8::new(238, 238, 238, 255));
+        assert_eq!(config.embed, Some(0x1a00003));
+        assert!(matches!(config.search_engine, EngineKind::Fuzzy));
+
+        let (_, config) = parse(&["-m", "1", "-w", "1234"]);
+        assert_eq!(config.monitor, Monitor::Index(1));
+        assert_eq!(config.embed, Some(1234));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for args in [
+            ["-l", "many"],
+            ["-nb", "222222"],
+            ["-w", "0xzz"],
+            ["-e", "psychic"],
+            ["-z", "wide"],
+        ] {
+            let (result, _) = parse(&args);
+            assert!(result.is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn rejects_missing_arguments_and_unknown_flags() {
+        let (result, _) = parse(&["-i", "-l"]);
+        assert_eq!(result.err().unwrap().to_string(), "-l requires an argument");
+
+        let (result, _) = parse(&["-q"]);
+        assert_eq!(result.err().unwrap().to_string(), "unknown option: -q");
+    }
+
+    #[test]
+    fn version_stops_parsing() {
+        let (result, config) = parse(&["-b", "-v", "-q", "-l"]);
+        assert!(matches!(result, Ok(Command::PrintVersion)));
+        assert!(matches!(config.position, Position::Bottom));
+    }
+
+    #[test]
+    fn takes_pango_and_dmenu_fonts() {
+        let (_, config) = parse(&["-fn", "Iosevka 11"]);
+        assert_eq!(config.font.as_deref(), Some("Iosevka 11"));
+        assert_eq!(config.font_size, DEFAULT_CONFIG.font_size);
+
+        let (_, config) = parse(&["-fn", "monospace:size=10"]);
+        assert_eq!(config.font.as_deref(), Some("monospace 10"));
+
+        let (_, config) = parse(&["-fn", "monospace:pixelsize=14"]);
+        assert_eq!(config.font.as_deref(), Some("monospace"));
+        assert_eq!(config.font_size, 14.0);
+
+        let (_, config) = parse(&["-fn", ":pixelsize=14:antialias=true"]);
+        assert_eq!(config.font, DEFAULT_CONFIG.font);
+        assert_eq!(config.font_size, 14.0);
+    }
+}
@@ -15,40 +15,265 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
-use rgb::RGB8;
+use rgb::RGBA8;
+
+use crate::keys::{Action, KeyBinding};
 
 #[derive(Debug, Copy, Clone)]
 pub enum Position {
     Top,
     Bottom,
+    /// floats in the middle of the monitor
+    Center,
+}
+
+impl Position {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "top" => Some(Position::Top),
+            "bottom" => Some(Position::Bottom),
+            "center" => Some(Position::Center),
+            _ => None,
+        }
+    }
+}
+
+/// A length that is either absolute or relative to the size of the monitor
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dimension {
+    Pixels(i32),
+    Percent(f64),
+}
+
+impl Dimension {
+    /// Takes "300" or "50%"
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().ok().map(Dimension::Percent),
+            None => value.trim().parse::<i32>().ok().map(Dimension::Pixels),
+        }
+    }
+
+    /// Only absolute sizes change with the scale
+    pub fn scaled(self, scale: f64) -> Self {
+        match self {
+            Dimension::Pixels(pixels) => Dimension::Pixels((pixels as f64 * scale).round() as i32),
+            percent => percent,
+        }
+    }
+
+    pub fn resolve(self, total: u16) -> i32 {
+        match self {
+            Dimension::Pixels(pixels) => pixels,
+            Dimension::Percent(percent) => (total as f64 * percent / 100.0).round() as i32,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum EngineKind {
+    Contains,
+    Fuzzy,
+    Prefix,
+    Tokens,
+    #[cfg(feature = "regex")]
+    Regex,
+}
+
+impl EngineKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "contains" => Some(EngineKind::Contains),
+            "fuzzy" => Some(EngineKind::Fuzzy),
+            "prefix" => Some(EngineKind::Prefix),
+            "tokens" => Some(EngineKind::Tokens),
+            #[cfg(feature = "regex")]
+            "regex" => Some(EngineKind::Regex),
+            _ => None,
+        }
+    }
+}
+
+/// Which monitor moemenu shows up on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Monitor {
+    /// the one with the focused window, falls back to the pointer
+    Focused,
+    /// the one with the mouse pointer
+    Pointer,
+    Index(usize),
+    /// RandR output name like "DP-1"
+    Name(String),
+}
+
+impl Monitor {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "focused" => Monitor::Focused,
+            "pointer" => Monitor::Pointer,
+            _ => match name.parse::<usize>() {
+                Ok(index) => Monitor::Index(index),
+                Err(_) => Monitor::Name(name.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum CaseMode {
+    Sensitive,
+    Insensitive,
+    /// only case sensitive if the search term contains an uppercase letter
+    Smart,
 }
 
 pub struct Colors {
-    pub background: RGB8,
-    pub font: RGB8,
-    pub selected_font: RGB8,
-    pub selected_background: RGB8,
+    pub background: RGBA8,
+    pub font: RGBA8,
+    pub selected_font: RGBA8,
+    pub selected_background: RGBA8,
+    pub match_font: RGBA8,
+    pub match_background: RGBA8,
+    pub prompt_font: RGBA8,
+    pub prompt_background: RGBA8,
+    pub border: RGBA8,
+}
+
+impl Colors {
+    /// Without a compositor the alpha channel is ignored, blends every color with black instead.
+    pub fn flatten(&mut self) {
+        let colors = [
+            &mut self.background,
+            &mut self.font,
+            &mut self.selected_font,
+            &mut self.selected_background,
+            &mut self.match_font,
+            &mut self.match_background,
+            &mut self.prompt_font,
+            &mut self.prompt_background,
+            &mut self.border,
+        ];
+        for color in colors {
+            let blend = |x: u8| (x as u16 * color.a as u16 / 255) as u8;
+            *color = RGBA8::new(blend(color.r), blend(color.g), blend(color.b), 255);
+        }
+    }
+}
+
+/// Parses "#rgb", "#rgba", "#rrggbb" and "#rrggbbaa", css colors like "rgba(0, 0, 0, 0.5)" need
+/// the config feature.
+pub fn parse_color(value: &str) -> Option<RGBA8> {
+    let hex = match value.trim().strip_prefix('#') {
+        Some(hex) => hex,
+        None => return parse_css_color(value),
+    };
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+
+    match digits.as_slice() {
+        [r, g, b] => Some(RGBA8::new(r * 17, g * 17, b * 17, 255)),
+        [r, g, b, a] => Some(RGBA8::new(r * 17, g * 17, b * 17, a * 17)),
+        [r1, r2, g1, g2, b1, b2] => Some(RGBA8::new(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, 255)),
+        [r1, r2, g1, g2, b1, b2, a1, a2] => Some(RGBA8::new(
+            r1 * 16 + r2,
+            g1 * 16 + g2,
+            b1 * 16 + b2,
+            a1 * 16 + a2,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "config")]
+fn parse_css_color(value: &str) -> Option<RGBA8> {
+    let color = value.parse::<css_color_parser::Color>().ok()?;
+    let alpha = (color.a.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(RGBA8::new(color.r, color.g, color.b, alpha))
+}
+
+#[cfg(not(feature = "config"))]
+fn parse_css_color(_value: &str) -> Option<RGBA8> {
+    None
 }
 
 pub struct Config {
     pub position: Position,
+    /// offset from the left edge of the monitor, or from the centered spot
+    pub x: Dimension,
+    /// offset from the top or bottom edge of the monitor, or from the centered spot
+    pub y: Dimension,
+    /// full width of the monitor if not set, half of it when centered
+    pub width: Option<Dimension>,
+    /// size in pixels for fonts that do not specify one
     pub font_size: f64,
     pub height: u16,
+    /// show the items in a vertical list with that many lines, 0 keeps them in a single row
+    pub lines: u16,
+    /// number of columns when the items are shown in a grid
+    pub columns: u16,
     pub colors: Colors,
+    pub border_width: u16,
+    /// space between the border and the content
+    pub padding: u16,
+    /// only rounds the window when a compositor is running
+    pub corner_radius: f64,
+    pub selection_radius: f64,
+    /// shrinks the box behind the selected item
+    pub selection_padding: f64,
     pub end_buffer: f64,
     pub item_spacing: f64,
     pub start_divisor: f64,
+    pub case: CaseMode,
+    pub search_engine: EngineKind,
+    /// Pango font description like "Iosevka 11", the default sans face if not set
+    pub font: Option<String>,
+    /// text shown in front of the search term
+    pub prompt: Option<String>,
+    pub monitor: Monitor,
+    /// multiplies every size, detected from Xft.dpi or the size of the monitor if not set
+    pub scale: Option<f64>,
+    /// id of a window to embed moemenu into, only set with -w
+    pub embed: Option<u32>,
+    /// applied on top of the default key bindings
+    pub keybindings: Vec<(KeyBinding, Action)>,
 }
 
 impl Config {
+    /// Multiplies every size in pixels, fonts with a size in points are scaled when drawing.
+    pub fn apply_scale(&mut self, scale: f64) {
+        let scale_u16 = |pixels: u16| (pixels as f64 * scale).round() as u16;
+        self.x = self.x.scaled(scale);
+        self.y = self.y.scaled(scale);
+        self.width = self.width.map(|width| width.scaled(scale));
+        self.font_size *= scale;
+        self.height = scale_u16(self.height);
+        self.border_width = scale_u16(self.border_width);
+        self.padding = scale_u16(self.padding);
+        self.corner_radius *= scale;
+        self.selection_radius *= scale;
+        self.selection_padding *= scale;
+        self.end_buffer *= scale;
+        self.item_spacing *= scale;
+        self.scale = Some(scale);
+    }
+
+    /// The defaults, overridden by the X resources and then by the config file.
+    pub fn get(resources: &str) -> Self {
+        let mut config = crate::defaults::DEFAULT_CONFIG;
+        crate::resources::apply(resources, &mut config);
+        Self::load(config)
+    }
+
     #[cfg(not(feature = "config"))]
-    pub fn get() -> Self {
-        crate::defaults::DEFAULT_CONFIG
+    fn load(base: Config) -> Self {
+        base
     }
 
     #[cfg(feature = "config")]
-    pub fn get() -> Self {
-        config_feature::load()
+    fn load(base: Config) -> Self {
+        config_feature::load(base)
     }
 }
 
@@ -56,29 +281,28 @@ impl Config {
 mod config_feature {
     use std::fs;
 
-    use css_color_parser::Color as CssColor;
-    use rgb::RGB8;
+    use rgb::RGBA8;
     use toml::value::Table;
     use toml::Value;
     use xdg::BaseDirectories;
 
-    use super::{Colors, Config, Position};
-    use crate::defaults::DEFAULT_CONFIG;
+    use super::{CaseMode, Colors, Config, Dimension, EngineKind, Monitor, Position};
+    use crate::keys::{Action, KeyBinding};
 
-    pub fn load() -> Config {
+    pub fn load(base: Config) -> Config {
         let xdg = BaseDirectories::new();
         if xdg.is_err() {
-            return DEFAULT_CONFIG;
+            return base;
         }
 
         let file = xdg.unwrap().find_config_file("moemenu.toml");
         if file.is_none() {
-            return DEFAULT_CONFIG;
+            return base;
         }
 
         let result = fs::read_to_string(file.unwrap());
         if result.is_err() {
-            return DEFAULT_CONFIG;
+            return base;
         }
 
         let config_text = result.unwrap();
@@ -86,36 +310,99 @@ mod config_feature {
         let values = config_text.parse::<Value>();
 
         match values {
-            Ok(values) => handle_toml(values),
-            Err(_) => DEFAULT_CONFIG,
+            Ok(values) => handle_toml(values, base),
+            Err(_) => base,
         }
     }
 
-    fn handle_toml(toml: Value) -> Config {
+    /// Values missing in the file are taken from base
+    pub(super) fn handle_toml(toml: Value, base: Config) -> Config {
         let position = get_str(&toml, "position");
         let font_size = get_float(&toml, "font_size");
         let height = get_int(&toml, "height");
+        let lines = get_int(&toml, "lines");
+        let columns = get_int(&toml, "columns");
+        let border_width = get_int(&toml, "border_width");
+        let padding = get_int(&toml, "padding");
+        let corner_radius = get_float(&toml, "corner_radius");
+        let selection_radius = get_float(&toml, "selection_radius");
+        let selection_padding = get_float(&toml, "selection_padding");
         let end_buffer = get_float(&toml, "end_buffer");
         let item_spacing = get_float(&toml, "item_spacing");
         let start_divisor = get_float(&toml, "start_divisor");
+        let case_sensitive = get_bool(&toml, "case_sensitive");
+        let smart_case = get_bool(&toml, "smart_case");
+        let search_engine = get_str(&toml, "search_engine");
+        let font = get_str(&toml, "font");
+        let prompt = get_str(&toml, "prompt");
+        let scale = get_float(&toml, "scale");
+        let monitor = match toml.get("monitor") {
+            Some(Value::Integer(index)) if *index >= 0 => Some(Monitor::Index(*index as usize)),
+            Some(Value::String(name)) => Some(Monitor::from_name(name)),
+            _ => None,
+        };
+        let x = get_dimension(&toml, "x");
+        let y = get_dimension(&toml, "y");
+        let width = get_dimension(&toml, "width");
         let colors = toml.get("colors");
+        let keybindings = toml.get("keybindings");
 
         Config {
-            position: match position.unwrap_or("top".to_string()).as_str() {
-                "top" => Position::Top,
-                "bottom" => Position::Bottom,
-                _ => Position::Bottom,
+            position: match position {
+                Some(name) => Position::from_name(&name).unwrap_or(Position::Bottom),
+                None => base.position,
+            },
+            x: x.unwrap_or(base.x),
+            y: y.unwrap_or(base.y),
+            width: width.or(base.width),
+            font_size: font_size.unwrap_or(base.font_size),
+            height: height.unwrap_or(base.height as i64) as u16,
+            lines: lines.unwrap_or(base.lines as i64) as u16,
+            columns: columns.unwrap_or(base.columns as i64) as u16,
+            border_width: border_width.unwrap_or(base.border_width as i64) as u16,
+            padding: padding.unwrap_or(base.padding as i64) as u16,
+            corner_radius: corner_radius.unwrap_or(base.corner_radius),
+            selection_radius: selection_radius.unwrap_or(base.selection_radius),
+            selection_padding: selection_padding.unwrap_or(base.selection_padding),
+            end_buffer: end_buffer.unwrap_or(base.end_buffer),
+            item_spacing: item_spacing.unwrap_or(base.item_spacing),
+            start_divisor: start_divisor.unwrap_or(base.start_divisor),
+            case: match (smart_case, case_sensitive) {
+                (Some(true), _) => CaseMode::Smart,
+                (_, Some(true)) => CaseMode::Sensitive,
+                (_, Some(false)) => CaseMode::Insensitive,
+                _ => base.case,
             },
-            font_size: font_size.unwrap_or(DEFAULT_CONFIG.font_size),
-            height: height.unwrap_or(DEFAULT_CONFIG.height as i64) as u16,
-            end_buffer: end_buffer.unwrap_or(DEFAULT_CONFIG.end_buffer),
-            item_spacing: item_spacing.unwrap_or(DEFAULT_CONFIG.item_spacing),
-            start_divisor: start_divisor.unwrap_or(DEFAULT_CONFIG.start_divisor),
-            colors: handle_colors(colors).unwrap_or(DEFAULT_CONFIG.colors),
+            search_engine: search_engine
+                .and_then(|name| EngineKind::from_name(&name))
+                .unwrap_or(base.search_engine),
+            font: font.or(base.font),
+            prompt: prompt.or(base.prompt),
+            monitor: monitor.unwrap_or(base.monitor),
+            scale: scale.filter(|scale| *scale > 0.0).or(base.scale),
+            embed: base.embed,
+            colors: handle_colors(colors, &base.colors).unwrap_or(base.colors),
+            keybindings: handle_keybindings(keybindings).unwrap_or(base.keybindings),
         }
     }
 
-    fn handle_colors(toml: Option<&Value>) -> Option<Colors> {
+    fn handle_keybindings(toml: Option<&Value>) -> Option<Vec<(KeyBinding, Action)>> {
+        let table = toml?.as_table()?;
+
+        // entries that can not be parsed are skipped like every other invalid value
+        let bindings = table
+            .iter()
+            .filter_map(|(key, action)| {
+                let binding = KeyBinding::parse(key)?;
+                let action = Action::from_name(action.as_str()?)?;
+                Some((binding, action))
+            })
+            .collect();
+
+        Some(bindings)
+    }
+
+    fn handle_colors(toml: Option<&Value>, base: &Colors) -> Option<Colors> {
         if toml.is_none() {
             return None;
         }
@@ -125,28 +412,31 @@ mod config_feature {
         let font = get_color_str(colors, "font");
         let selected_background = get_color_str(colors, "selected_background");
         let selected_font = get_color_str(colors, "selected_font");
+        let match_font = get_color_str(colors, "match_font");
+        let match_background = get_color_str(colors, "match_background");
+        let prompt_font = get_color_str(colors, "prompt_font");
+        let prompt_background = get_color_str(colors, "prompt_background");
+        let border = get_color_str(colors, "border");
 
         Some(Colors {
-            background: parse_color(background, DEFAULT_CONFIG.colors.background),
-            font: parse_color(font, DEFAULT_CONFIG.colors.font),
-            selected_font: parse_color(selected_font, DEFAULT_CONFIG.colors.selected_font),
-            selected_background: parse_color(
-                selected_background,
-                DEFAULT_CONFIG.colors.selected_background,
-            ),
+            background: parse_color(background, base.background),
+            font: parse_color(font, base.font),
+            selected_font: parse_color(selected_font, base.selected_font),
+            selected_background: parse_color(selected_background, base.selected_background),
+            match_font: parse_color(match_font, base.match_font),
+            match_background: parse_color(match_background, base.match_background),
+            prompt_font: parse_color(prompt_font, base.prompt_font),
+            prompt_background: parse_color(prompt_background, base.prompt_background),
+            border: parse_color(border, base.border),
         })
     }
 
     // pile of util functions, LETS GO!
-    fn parse_color(maybe: Option<String>, fallback: RGB8) -> RGB8 {
+    fn parse_color(maybe: Option<String>, fallback: RGBA8) -> RGBA8 {
         if maybe.is_none() {
             return fallback;
         }
-        let color = maybe.unwrap().parse::<CssColor>();
-        match color {
-            Ok(c) => RGB8::new(c.r, c.g, c.b),
-            Err(_) => fallback,
-        }
+        super::parse_color(&maybe.unwrap()).unwrap_or(fallback)
     }
 
     fn get_str(toml: &Value, key: &str) -> Option<String> {
@@ -160,29 +450,31 @@ mod config_feature {
     }
 
     fn get_int(toml: &Value, key: &str) -> Option<i64> {
-        match toml.get(key) {
-            Some(val) => {
-                if val.as_integer().is_none() {
-                    if let Some(float) = get_float(toml, key) {
-                        return Some(float as i64);
-                    }
-                }
-                val.as_integer()
-            }
-            None => None,
+        match toml.get(key)? {
+            Value::Integer(int) => Some(*int),
+            Value::Float(float) => Some(*float as i64),
+            _ => None,
         }
     }
 
     fn get_float(toml: &Value, key: &str) -> Option<f64> {
+        match toml.get(key)? {
+            Value::Float(float) => Some(*float),
+            Value::Integer(int) => Some(*int as f64),
+            _ => None,
+        }
+    }
+
+    fn get_dimension(toml: &Value, key: &str) -> Option<Dimension> {
+        match toml.get(key)? {
+            Value::String(value) => Dimension::parse(value),
+            _ => get_int(toml, key).map(|pixels| Dimension::Pixels(pixels as i32)),
+        }
+    }
+
+    fn get_bool(toml: &Value, key: &str) -> Option<bool> {
         match toml.get(key) {
-            Some(val) => {
-                if val.as_float().is_none() {
-                    if let Some(int) = get_int(toml, key) {
-                        return Some(int as f64);
-                    }
-                }
-                val.as_float()
-            }
+            Some(val) => val.as_bool(),
             None => None,
         }
     }
@@ -197,3 +489,53 @@ mod config_feature {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors_with_alpha() {
+        assert_eq!(parse_color("#fff"), Some(RGBA8::new(255, 255, 255, 255)));
+        assert_eq!(parse_color("#0008"), Some(RGBA8::new(0, 0, 0, 136)));
+        assert_eq!(parse_color("#222222"), Some(RGBA8::new(34, 34, 34, 255)));
+        assert_eq!(parse_color("#22222280"), Some(RGBA8::new(34, 34, 34, 128)));
+        assert_eq!(parse_color("#22222"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn parses_css_rgba() {
+        assert_eq!(
+            parse_color("rgba(34, 34, 34, 0.5)"),
+            Some(RGBA8::new(34, 34, 34, 128))
+        );
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn parses_every_monitor_form() {
+        let monitor = |text: &str| {
+            let toml = text.parse::<toml::Value>().unwrap();
+            config_feature::handle_toml(toml, crate::defaults::DEFAULT_CONFIG).monitor
+        };
+        assert_eq!(monitor("monitor = 1"), Monitor::Index(1));
+        assert_eq!(monitor("monitor = \"pointer\""), Monitor::Pointer);
+        assert_eq!(monitor("monitor = \"focused\""), Monitor::Focused);
+        assert_eq!(
+            monitor("monitor = \"DP-1\""),
+            Monitor::Name("DP-1".to_string())
+        );
+        assert_eq!(monitor("height = \"tall\""), Monitor::Focused);
+    }
+
+    #[test]
+    fn flattens_against_black() {
+        let mut colors = crate::defaults::DEFAULT_CONFIG.colors;
+        colors.background = RGBA8::new(200, 100, 0, 128);
+        colors.flatten();
+        assert_eq!(colors.background, RGBA8::new(100, 50, 0, 255));
+        assert_eq!(colors.font, RGBA8::new(0, 0, 0, 255));
+    }
+}
@@ -15,24 +15,49 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
-use crate::config::{Colors, Config, Position};
-use rgb::RGB8;
+use crate::config::{CaseMode, Colors, Config, Dimension, EngineKind, Monitor, Position};
+use rgb::RGBA8;
 
-const PINK: RGB8 = RGB8::new(247, 168, 184);
-const BLACK: RGB8 = RGB8::new(0, 0, 0);
-const WHITE: RGB8 = RGB8::new(255, 255, 255);
+const PINK: RGBA8 = RGBA8::new(247, 168, 184, 255);
+const BLACK: RGBA8 = RGBA8::new(0, 0, 0, 255);
+const WHITE: RGBA8 = RGBA8::new(255, 255, 255, 255);
+const DEEP_PINK: RGBA8 = RGBA8::new(199, 21, 133, 255);
+const LIGHT_PINK: RGBA8 = RGBA8::new(255, 214, 224, 255);
 
 pub const DEFAULT_CONFIG: Config = Config {
     position: Position::Top,
+    x: Dimension::Pixels(0),
+    y: Dimension::Pixels(0),
+    width: None,
     font_size: 13.0,
     height: 26,
+    lines: 0,
+    columns: 1,
+    border_width: 0,
+    padding: 0,
+    corner_radius: 0.0,
+    selection_radius: 0.0,
+    selection_padding: 0.0,
     end_buffer: 20.0,
     item_spacing: 20.0,
     start_divisor: 6.0,
+    case: CaseMode::Sensitive,
+    search_engine: EngineKind::Contains,
+    font: None,
+    prompt: None,
+    monitor: Monitor::Focused,
+    scale: None,
+    embed: None,
+    keybindings: Vec::new(),
     colors: Colors {
         background: PINK,
         font: BLACK,
         selected_font: BLACK,
         selected_background: WHITE,
+        match_font: DEEP_PINK,
+        match_background: LIGHT_PINK,
+        prompt_font: BLACK,
+        prompt_background: WHITE,
+        border: DEEP_PINK,
     },
 };
@@ -15,56 +15,159 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+use std::f64::consts::PI;
+use std::ops::Range;
+
 use crate::{Config, Menu};
-use rgb::RGB8;
+use rgb::RGBA8;
+
+const PROMPT_PADDING: f64 = 10.0;
 
-pub fn set_color(cr: &cairo::Context, rgb: RGB8) {
+/// cairo premultiplies the alpha for the ARGB visual, a compositor blends the window from that.
+pub fn set_color(cr: &cairo::Context, rgba: RGBA8) {
     let convert = |x| 1.0 / 255.0 * (x as f64);
-    cr.set_source_rgb(convert(rgb.r), convert(rgb.g), convert(rgb.b));
+    cr.set_source_rgba(
+        convert(rgba.r),
+        convert(rgba.g),
+        convert(rgba.b),
+        convert(rgba.a),
+    );
+}
+
+/// Sizes that are not in the config still follow its scale.
+fn scaled(pixels: f64, config: &Config) -> f64 {
+    pixels * config.scale.unwrap_or(1.0)
+}
+
+/// Font of the config, font_size in pixels is used if the description has no size.
+pub fn font_description(config: &Config) -> pango::FontDescription {
+    let mut description =
+        pango::FontDescription::from_string(config.font.as_deref().unwrap_or("sans"));
+    let size = description.get_size();
+    if size == 0 {
+        description.set_absolute_size(config.font_size * pango::SCALE as f64);
+    } else if description.get_size_is_absolute() {
+        description.set_absolute_size(scaled(size as f64, config));
+    } else {
+        description.set_size(scaled(size as f64, config).round() as i32);
+    }
+    description
+}
+
+/// Shapes text with Pango, fontconfig fills in glyphs the font does not have.
+pub struct Text {
+    layout: pango::Layout,
+}
+
+impl Text {
+    pub fn new(cr: &cairo::Context, config: &Config) -> Self {
+        let layout = pangocairo::create_layout(cr).expect("failed to create a pango layout");
+        layout.set_font_description(Some(&font_description(config)));
+        Text { layout }
+    }
+
+    pub fn width(&self, text: &str) -> f64 {
+        self.layout.set_text(text);
+        self.layout.get_pixel_size().0 as f64
+    }
+
+    /// Draws text vertically centered in a line starting at top, returns where the text starts.
+    fn show(&self, cr: &cairo::Context, text: &str, (x, top): (f64, f64), line_height: f64) -> f64 {
+        self.layout.set_text(text);
+        let y = top + (line_height - self.height()) / 2.0;
+        cr.move_to(x, y);
+        pangocairo::show_layout(cr, &self.layout);
+        y
+    }
+
+    /// Height of the last text
+    fn height(&self) -> f64 {
+        self.layout.get_pixel_size().1 as f64
+    }
+
+    /// Horizontal offset of a byte index of the last text
+    fn offset(&self, index: usize) -> f64 {
+        self.layout.index_to_pos(index as i32).x as f64 / pango::SCALE as f64
+    }
+}
+
+/// Space taken by the border and the padding on every side of the content.
+pub fn inset(config: &Config) -> u16 {
+    config.border_width.saturating_add(config.padding)
+}
+
+/// Adds a rectangle with rounded corners to the path of the context.
+fn rounded_rectangle(cr: &cairo::Context, rect: &Rect, radius: f64) {
+    let radius = radius.min(rect.width / 2.0).min(rect.height / 2.0);
+    if radius <= 0.0 {
+        cr.rectangle(rect.x, rect.y, rect.width, rect.height);
+        return;
+    }
+
+    let (left, top) = (rect.x + radius, rect.y + radius);
+    let (right, bottom) = (rect.x + rect.width - radius, rect.y + rect.height - radius);
+    cr.new_sub_path();
+    cr.arc(right, top, radius, -PI / 2.0, 0.0);
+    cr.arc(right, bottom, radius, 0.0, PI / 2.0);
+    cr.arc(left, bottom, radius, PI / 2.0, PI);
+    cr.arc(left, top, radius, PI, 1.5 * PI);
+    cr.close_path();
+}
+
+/// Width of the prompt box including its padding, 0 without a prompt.
+pub fn prompt_width(measure: &dyn Fn(&str) -> f64, config: &Config) -> f64 {
+    match &config.prompt {
+        Some(prompt) => measure(prompt) + 2.0 * scaled(PROMPT_PADDING, config),
+        None => 0.0,
+    }
+}
+
+/// Where the items start when they are shown next to the search term.
+fn items_start(measure: &dyn Fn(&str) -> f64, width: f64, config: &Config) -> f64 {
+    prompt_width(measure, config) + width / config.start_divisor
 }
 
 pub fn find_last_item_that_fits(
-    cr: &cairo::Context,
+    measure: &dyn Fn(&str) -> f64,
     width: f64,
     start: usize,
     config: &Config,
-    items: &Vec<String>,
+    items: &[String],
 ) -> usize {
-    let mut position: f64 = width / config.start_divisor;
+    let mut position: f64 = items_start(measure, width, config);
 
-    for (i, item) in items.clone().iter().enumerate() {
+    for (i, item) in items.iter().enumerate() {
         // is the item to the left of the starting point?
         if i < start {
             continue;
         }
 
-        let text_extents = cr.text_extents(item);
+        let item_width = measure(item);
         let next_width = match items.get(i + 1) {
-            Some(word) => cr.text_extents(word).width,
+            Some(word) => measure(word),
             None => 0.0,
         };
-        let next_is_off_screen =
-            position + text_extents.width + next_width + 2.0 * config.item_spacing
-                > width - config.end_buffer;
+        let next_is_off_screen = position + item_width + next_width + 2.0 * config.item_spacing
+            > width - config.end_buffer;
 
         if next_is_off_screen {
             return i;
         }
 
-        position += text_extents.width + config.item_spacing;
+        position += item_width + config.item_spacing;
     }
 
     items.len()
 }
 
 pub fn find_first_item_that_fits(
-    cr: &cairo::Context,
+    measure: &dyn Fn(&str) -> f64,
     width: f64,
     end: usize,
     config: &Config,
-    items: &Vec<String>,
+    items: &[String],
 ) -> usize {
-    let start: f64 = width / config.start_divisor;
+    let start: f64 = items_start(measure, width, config);
     let mut position: f64 = width - config.end_buffer;
     let len = items.len();
 
@@ -74,25 +177,307 @@ pub fn find_first_item_that_fits(
             continue;
         }
 
-        let item = items.get(i).unwrap();
-        let text_extents = cr.text_extents(item);
+        let item_width = measure(&items[i]);
         let previous_width = match items.get((i + len - 1) % len) {
-            Some(word) => cr.text_extents(word).width,
+            Some(word) => measure(word),
             None => 0.0,
         };
         let prev_is_off_screen =
-            position - text_extents.width - previous_width - 2.0 * config.item_spacing < start;
+            position - item_width - previous_width - 2.0 * config.item_spacing < start;
 
         if prev_is_off_screen {
             return i;
         }
 
-        position -= text_extents.width - config.item_spacing;
+        position -= item_width - config.item_spacing;
     }
 
     0
 }
 
+/// An area of the window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.x <= x && x < self.x + self.width && self.y <= y && y < self.y + self.height
+    }
+}
+
+/// The area of the window an item is drawn into
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemBox {
+    pub index: usize,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// where the text of the item starts
+    pub text_x: f64,
+}
+
+impl ItemBox {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let rect = Rect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        };
+        rect.contains(x, y)
+    }
+}
+
+/// What is under a point of the window
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Hit {
+    Item(usize),
+    PreviousPage,
+    NextPage,
+    Nothing,
+}
+
+/// The items that are currently visible and how to get to the other pages
+#[derive(Debug)]
+pub struct Page {
+    pub items: Vec<ItemBox>,
+    /// first item of the previous page
+    pub previous_start: usize,
+    /// number of items on a page, None if it depends on the width of the items
+    pub size: Option<usize>,
+    /// the "<" and ">" that are shown in a single row when there are more pages
+    pub previous_indicator: Option<Rect>,
+    pub next_indicator: Option<Rect>,
+}
+
+impl Page {
+    pub fn last_item(&self) -> usize {
+        match self.items.last() {
+            Some(item) => item.index,
+            None => 0,
+        }
+    }
+
+    /// Finds what was clicked.
+    pub fn hit(&self, x: f64, y: f64) -> Hit {
+        let indicator_hit = |indicator: &Option<Rect>| match indicator {
+            Some(rect) => rect.contains(x, y),
+            None => false,
+        };
+
+        if indicator_hit(&self.previous_indicator) {
+            return Hit::PreviousPage;
+        }
+        if indicator_hit(&self.next_indicator) {
+            return Hit::NextPage;
+        }
+
+        match self.items.iter().find(|item| item.contains(x, y)) {
+            Some(item) => Hit::Item(item.index),
+            None => Hit::Nothing,
+        }
+    }
+}
+
+/// How the items are arranged, a single row and a single column are special cases of the grid
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Layout {
+    Horizontal,
+    Grid { columns: usize, rows: usize },
+}
+
+impl Layout {
+    pub fn new(config: &Config) -> Self {
+        if config.lines == 0 && config.columns <= 1 {
+            return Layout::Horizontal;
+        }
+
+        Layout::Grid {
+            columns: config.columns.max(1) as usize,
+            rows: config.lines.max(1) as usize,
+        }
+    }
+
+    /// Number of lines the window needs, including the one for the search term.
+    pub fn lines(&self) -> u16 {
+        match self {
+            Layout::Horizontal => 1,
+            Layout::Grid { rows, .. } => *rows as u16 + 1,
+        }
+    }
+
+    /// How far Up and Down move the selection.
+    pub fn columns(&self) -> usize {
+        match self {
+            Layout::Horizontal => 1,
+            Layout::Grid { columns, .. } => *columns,
+        }
+    }
+}
+
+/// Positions the items of the current page, measure returns the width of a text.
+pub fn layout_page(
+    measure: &dyn Fn(&str) -> f64,
+    width: f64,
+    config: &Config,
+    items: &[String],
+    shift: usize,
+) -> Page {
+    let line_height = config.height as f64;
+
+    match Layout::new(config) {
+        Layout::Horizontal => {
+            let spacing = config.item_spacing;
+            let last = find_last_item_that_fits(measure, width, shift, config, items);
+            let end = (last + 1).min(items.len());
+            let mut position = items_start(measure, width, config);
+            let mut boxes = Vec::new();
+
+            let start = position;
+            for (i, item) in items.iter().enumerate().take(end).skip(shift) {
+                let item_width = measure(item);
+                boxes.push(ItemBox {
+                    index: i,
+                    x: position - spacing / 2.0,
+                    y: 0.0,
+                    width: item_width + spacing,
+                    height: line_height,
+                    text_x: position,
+                });
+                position += item_width + spacing;
+            }
+
+            // the indicators reach up to the items so they are easy to click
+            let previous_indicator = match shift > 0 {
+                true => {
+                    let x = start - measure("<") - spacing;
+                    Some(Rect {
+                        x,
+                        y: 0.0,
+                        width: start - spacing / 2.0 - x,
+                        height: line_height,
+                    })
+                }
+                false => None,
+            };
+            let next_indicator = match end < items.len() {
+                true => Some(Rect {
+                    x: width - config.end_buffer,
+                    y: 0.0,
+                    width: config.end_buffer,
+                    height: line_height,
+                }),
+                false => None,
+            };
+
+            Page {
+                items: boxes,
+                previous_start: find_first_item_that_fits(measure, width, shift, config, items),
+                size: None,
+                previous_indicator,
+                next_indicator,
+            }
+        }
+        Layout::Grid { columns, rows } => {
+            let size = columns * rows;
+            let cell_width = width / columns as f64;
+            let end = (shift + size).min(items.len());
+
+            let boxes = (shift..end)
+                .map(|i| {
+                    let (row, column) = ((i - shift) / columns, (i - shift) % columns);
+                    let x = cell_width * column as f64;
+                    ItemBox {
+                        index: i,
+                        x,
+                        y: line_height * (row + 1) as f64,
+                        width: cell_width,
+                        height: line_height,
+                        text_x: x + scaled(10.0, config),
+                    }
+                })
+                .collect();
+
+            Page {
+                items: boxes,
+                previous_start: shift.saturating_sub(size),
+                size: Some(size),
+                previous_indicator: None,
+                next_indicator: None,
+            }
+        }
+    }
+}
+
+/// Lays out the current page of the menu with the font of the context, inside the border and
+/// padding of a window that is width wide.
+pub fn current_page(cr: &cairo::Context, width: f64, config: &Config, menu: &Menu) -> Page {
+    let width = width - 2.0 * inset(config) as f64;
+    let text = Text::new(cr, config);
+    let measure = |item: &str| text.width(item);
+    layout_page(&measure, width, config, menu.get_items(), menu.get_shift())
+}
+
+/// Draws an item with the parts that matched the search term highlighted.
+fn draw_item(
+    cr: &cairo::Context,
+    text: &Text,
+    item: &str,
+    spans: &[Range<usize>],
+    (x, top): (f64, f64),
+    font_color: RGBA8,
+    config: &Config,
+) {
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for span in spans {
+        // spans of different tokens can overlap
+        let start = span.start.max(last);
+        if start >= span.end {
+            continue;
+        }
+        if start > last {
+            segments.push((last..start, false));
+        }
+        segments.push((start..span.end, true));
+        last = span.end;
+    }
+    if last < item.len() {
+        segments.push((last..item.len(), false));
+    }
+
+    let line_height = config.height as f64;
+    text.layout.set_text(item);
+    let y = top + (line_height - text.height()) / 2.0;
+    for (range, matched) in segments {
+        // the whole item is drawn clipped to each part so shaping and kerning stay intact
+        let (start, end) = (text.offset(range.start), text.offset(range.end));
+        let (left, right) = (x + start.min(end), x + start.max(end));
+
+        cr.save();
+        cr.rectangle(left, top, right - left, line_height);
+        cr.clip();
+        if matched {
+            set_color(cr, config.colors.match_background);
+            cr.rectangle(left, y, right - left, text.height());
+            cr.fill();
+            set_color(cr, config.colors.match_font);
+        } else {
+            set_color(cr, font_color);
+        }
+
+        cr.move_to(x, y);
+        pangocairo::show_layout(cr, &text.layout);
+        cr.restore();
+    }
+}
+
 pub fn do_draw(
     cr: &cairo::Context,
     (width, height): (f64, f64),
@@ -100,102 +485,239 @@ pub fn do_draw(
     config: &Config,
     menu: &Menu,
 ) {
-    // draw bar
-    if transparency {
+    // draw bar, the corners stay see through with a compositor
+    let radius = if transparency {
         cr.set_operator(cairo::Operator::Source);
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.0);
+        cr.paint();
+        config.corner_radius
+    } else {
+        0.0
+    };
+    let window = Rect {
+        x: 0.0,
+        y: 0.0,
+        width,
+        height,
+    };
+    if config.border_width > 0 {
+        set_color(cr, config.colors.border);
+        rounded_rectangle(cr, &window, radius);
+        cr.fill();
     }
+    let border = config.border_width as f64;
+    let inner = Rect {
+        x: border,
+        y: border,
+        width: width - 2.0 * border,
+        height: height - 2.0 * border,
+    };
     set_color(cr, config.colors.background);
-    cr.paint();
+    rounded_rectangle(cr, &inner, radius - border);
+    cr.fill();
 
     if transparency {
         cr.set_operator(cairo::Operator::Over);
     }
 
+    // everything else is drawn inside the border and padding
+    let page = current_page(cr, width, config, menu);
+    let inset = inset(config) as f64;
+    let (width, height) = (width - 2.0 * inset, height - 2.0 * inset);
+    cr.save();
+    cr.translate(inset, inset);
+
     // print items
     set_color(cr, config.colors.font);
-    cr.set_font_size(config.font_size);
-    let font_extents = cr.font_extents();
-
-    let start: f64 = width / config.start_divisor;
-    let mut position: f64 = start;
+    let text = Text::new(cr, config);
+    let line_height = config.height as f64;
     let spacing = config.item_spacing;
-    let current_selection = menu.get_selection();
+    draw_page(cr, &text, &page, config, menu);
+
+    draw_page_indicators(cr, &text, height, &page, config);
+
+    let measure = |item: &str| text.width(item);
+    let prompt_end = prompt_width(&measure, config);
+    if let Some(prompt) = &config.prompt {
+        set_color(cr, config.colors.prompt_background);
+        cr.rectangle(0.0, 0.0, prompt_end, line_height);
+        cr.fill();
+        set_color(cr, config.colors.prompt_font);
+        text.show(
+            cr,
+            prompt,
+            (scaled(PROMPT_PADDING, config), 0.0),
+            line_height,
+        );
+    }
 
-    // has previous page
-    if menu.get_shift() > 0 {
-        let prev_page_indicator = "<";
-        let ppi_extents = cr.text_extents(prev_page_indicator);
-        cr.move_to(
-            start - ppi_extents.width - spacing,
-            ppi_extents.height + (height - ppi_extents.height) / 2.0,
+    // print search_term, with the text of the input method at the cursor
+    set_color(cr, config.colors.font);
+    let term = menu.get_search_term();
+    let (preedit, preedit_cursor) = menu.get_preedit();
+    let (before, after) = term.split_at(menu.get_cursor());
+    let term_text = format!("{}{}{}", before, preedit, after);
+    let text_x = prompt_end + scaled(10.0, config);
+    let line_width = scaled(1.0, config).round();
+    let text_y = text.show(cr, &term_text, (text_x, 0.0), line_height);
+
+    let text_height = text.height();
+    let before_x = text_x + text.offset(before.len());
+    if !preedit.is_empty() {
+        // underline the text that is still being composed
+        let preedit_end = text_x + text.offset(before.len() + preedit.len());
+        cr.rectangle(
+            before_x,
+            text_y + text_height - line_width,
+            preedit_end - before_x,
+            line_width,
         );
-        cr.show_text(prev_page_indicator);
+        cr.fill();
     }
 
+    // draw the text cursor
+    let cursor_x = text_x + text.offset(before.len() + preedit_cursor);
+    cr.rectangle(cursor_x, text_y, line_width, text_height);
+    cr.fill();
+
+    // print errors of the search engine over the items
+    if let Some(error) = menu.get_search_error() {
+        let x = width - text.width(&error) - config.end_buffer;
+        set_color(cr, config.colors.background);
+        cr.rectangle(
+            x - spacing / 2.0,
+            0.0,
+            width - x + spacing / 2.0,
+            line_height,
+        );
+        cr.fill();
+        set_color(cr, config.colors.font);
+        text.show(cr, &error, (x, 0.0), line_height);
+    }
+
+    cr.restore();
+}
+
+fn draw_page(cr: &cairo::Context, text: &Text, page: &Page, config: &Config, menu: &Menu) {
     let items = menu.get_items();
-    let mut has_next_page = false;
-    for (i, item) in items.clone().iter().enumerate() {
-        // don't draw elements that have been scrolled away
-        if i < menu.get_shift() as usize {
-            continue;
-        }
+    let current_selection = menu.get_selection();
 
-        let text_extents = cr.text_extents(item);
+    for item_box in &page.items {
+        let i = item_box.index;
 
         // draw background for selected item
-        if i == current_selection as usize {
+        let font_color = if i == current_selection {
             set_color(cr, config.colors.selected_background);
-            cr.move_to(position, 0.0);
-            cr.rectangle(
-                position - spacing / 2.0,
-                0.0,
-                text_extents.width + spacing,
-                height,
-            );
+            let padding = config.selection_padding;
+            let selection = Rect {
+                x: item_box.x + padding,
+                y: item_box.y + padding,
+                width: item_box.width - 2.0 * padding,
+                height: item_box.height - 2.0 * padding,
+            };
+            rounded_rectangle(cr, &selection, config.selection_radius);
             cr.fill();
-        }
-
-        if i == current_selection as usize {
-            set_color(cr, config.colors.selected_font);
+            config.colors.selected_font
         } else {
-            set_color(cr, config.colors.font);
-        }
-
-        let next_width = match items.get(i + 1) {
-            Some(word) => cr.text_extents(word).width,
-            None => 0.0,
+            config.colors.font
         };
-        let next_is_off_screen =
-            position + text_extents.width + next_width + 2.0 * spacing > width - config.end_buffer;
 
-        let y_pos = height / 2.0 + config.font_size / 2.0 - font_extents.descent * 0.7; // 0.7 for good measure
-        cr.move_to(position, y_pos);
-        cr.show_text(item);
+        // long items should not spill into the next cell
+        cr.save();
+        cr.rectangle(item_box.x, item_box.y, item_box.width, item_box.height);
+        cr.clip();
+        let spans = menu.get_match_spans(i);
+        draw_item(
+            cr,
+            text,
+            &items[i],
+            spans,
+            (item_box.text_x, item_box.y),
+            font_color,
+            config,
+        );
+        cr.restore();
+    }
+}
 
-        if next_is_off_screen {
-            has_next_page = true;
-            break;
+fn draw_page_indicators(
+    cr: &cairo::Context,
+    text: &Text,
+    height: f64,
+    page: &Page,
+    config: &Config,
+) {
+    set_color(cr, config.colors.font);
+
+    let indicators = [(&page.previous_indicator, "<"), (&page.next_indicator, ">")];
+    for (indicator, arrow) in indicators.iter() {
+        if let Some(rect) = indicator {
+            text.show(cr, arrow, (rect.x, 0.0), height);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::DEFAULT_CONFIG;
 
-        position += text_extents.width + spacing;
+    // every char is 10 pixels wide, "item0" is 50
+    fn measure(text: &str) -> f64 {
+        text.chars().count() as f64 * 10.0
     }
 
-    if has_next_page {
-        let next_page_indicator = ">";
-        let npi_extents = cr.text_extents(next_page_indicator);
-        cr.move_to(
-            width - config.end_buffer,
-            npi_extents.height + (height - npi_extents.height) / 2.0,
-        );
-        cr.show_text(next_page_indicator);
+    fn items(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("item{}", i)).collect()
     }
 
-    // print search_term
-    let term = menu.get_search_term();
-    let term_extents = cr.text_extents(&term);
-    cr.move_to(
-        10.0,
-        term_extents.height + (height - term_extents.height) / 2.0,
-    );
-    cr.show_text(&term);
+    #[test]
+    fn clicks_in_a_single_row_hit_items_and_indicators() {
+        let config = DEFAULT_CONFIG;
+        // the items start at 600 / start_divisor and are 50 + item_spacing apart
+        let page = layout_page(&measure, 600.0, &config, &items(10), 0);
+
+        assert_eq!(page.hit(105.0, 10.0), Hit::Item(0));
+        assert_eq!(page.hit(245.0, 10.0), Hit::Item(2));
+        // the search term
+        assert_eq!(page.hit(50.0, 10.0), Hit::Nothing);
+        assert_eq!(page.hit(590.0, 10.0), Hit::NextPage);
+        assert_eq!(page.previous_indicator, None);
+        // below the window
+        assert_eq!(page.hit(105.0, 30.0), Hit::Nothing);
+    }
+
+    #[test]
+    fn clicks_left_of_a_shifted_row_go_to_the_previous_page() {
+        let config = DEFAULT_CONFIG;
+        let page = layout_page(&measure, 600.0, &config, &items(10), 3);
+
+        assert_eq!(page.hit(75.0, 10.0), Hit::PreviousPage);
+        assert_eq!(page.hit(105.0, 10.0), Hit::Item(3));
+    }
+
+    #[test]
+    fn clicks_in_a_grid_hit_the_cell() {
+        let mut config = DEFAULT_CONFIG;
+        config.lines = 2;
+        config.columns = 3;
+        let page = layout_page(&measure, 600.0, &config, &items(12), 6);
+
+        // the first line belongs to the search term
+        assert_eq!(page.hit(10.0, 10.0), Hit::Nothing);
+        assert_eq!(page.hit(10.0, 30.0), Hit::Item(6));
+        // second row, third column
+        assert_eq!(page.hit(450.0, 60.0), Hit::Item(11));
+        assert_eq!(page.next_indicator, None);
+    }
+
+    #[test]
+    fn clicks_on_empty_cells_hit_nothing() {
+        let mut config = DEFAULT_CONFIG;
+        config.lines = 3;
+        let page = layout_page(&measure, 600.0, &config, &items(2), 0);
+
+        assert_eq!(page.hit(300.0, 60.0), Hit::Item(1));
+        assert_eq!(page.hit(300.0, 90.0), Hit::Nothing);
+    }
 }
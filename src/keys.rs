@@ -0,0 +1,490 @@
+/*
+ * This file is part of moemenu.
+ * Copyright (C) 2021 fence.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+
+use x11rb::protocol::xproto::Keycode;
+
+pub type Keysym = u32;
+
+/// The keysyms moemenu cares about, see X11/keysymdef.h
+#[allow(non_upper_case_globals)]
+pub mod keysym {
+    use super::Keysym;
+
+    pub const BackSpace: Keysym = 0xff08;
+    pub const Tab: Keysym = 0xff09;
+    pub const Return: Keysym = 0xff0d;
+    pub const Escape: Keysym = 0xff1b;
+    pub const Home: Keysym = 0xff50;
+    pub const Left: Keysym = 0xff51;
+    pub const Up: Keysym = 0xff52;
+    pub const Right: Keysym = 0xff53;
+    pub const Down: Keysym = 0xff54;
+    pub const Prior: Keysym = 0xff55;
+    pub const Next: Keysym = 0xff56;
+    pub const End: Keysym = 0xff57;
+    pub const Insert: Keysym = 0xff63;
+    pub const KP_Enter: Keysym = 0xff8d;
+    pub const F1: Keysym = 0xffbe;
+    pub const Delete: Keysym = 0xffff;
+}
+
+const NAMED_KEYSYMS: &[(&str, Keysym)] = &[
+    ("BackSpace", keysym::BackSpace),
+    ("Tab", keysym::Tab),
+    ("Return", keysym::Return),
+    ("Enter", keysym::Return),
+    ("Escape", keysym::Escape),
+    ("Home", keysym::Home),
+    ("Left", keysym::Left),
+    ("Up", keysym::Up),
+    ("Right", keysym::Right),
+    ("Down", keysym::Down),
+    ("Prior", keysym::Prior),
+    ("Page_Up", keysym::Prior),
+    ("Next", keysym::Next),
+    ("Page_Down", keysym::Next),
+    ("End", keysym::End),
+    ("Insert", keysym::Insert),
+    ("KP_Enter", keysym::KP_Enter),
+    ("Delete", keysym::Delete),
+    ("space", 0x20),
+];
+
+/// Looks up a keysym by its X11 name, single characters are their own name.
+pub fn keysym_from_name(name: &str) -> Option<Keysym> {
+    if let Some((_, sym)) = NAMED_KEYSYMS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+    {
+        return Some(*sym);
+    }
+
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+        if (1..=35).contains(&n) {
+            return Some(keysym::F1 + n - 1);
+        }
+    }
+
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(char_to_keysym(c)),
+        _ => None,
+    }
+}
+
+/// Latin-1 keysyms are equal to their code point, everything else uses the unicode range.
+pub fn char_to_keysym(c: char) -> Keysym {
+    match c as u32 {
+        cp @ 0x20..=0x7e | cp @ 0xa0..=0xff => cp,
+        cp => 0x0100_0000 | cp,
+    }
+}
+
+pub type Modifiers = u16;
+
+/// The modifier bits of the X11 key event state
+pub mod modifier {
+    use super::Modifiers;
+
+    pub const SHIFT: Modifiers = 0x1;
+    pub const CONTROL: Modifiers = 0x4;
+    pub const ALT: Modifiers = 0x8;
+    pub const SUPER: Modifiers = 0x40;
+
+    /// only these take part in matching key bindings, caps and num lock are ignored
+    pub const RELEVANT: Modifiers = SHIFT | CONTROL | ALT | SUPER;
+
+    /// XKB reports the active keyboard group in bits 13 and 14 of the state
+    pub fn group(state: Modifiers) -> u8 {
+        ((state >> 13) & 0x3) as u8
+    }
+}
+
+/// A key combination like "ctrl+shift+Return"
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub modifiers: Modifiers,
+    /// keysym of the key without any modifiers applied
+    pub keysym: Keysym,
+}
+
+impl KeyBinding {
+    pub const fn new(modifiers: Modifiers, keysym: Keysym) -> Self {
+        KeyBinding { modifiers, keysym }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        // "ctrl++" binds the plus key
+        if text.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+
+        let key = parts.pop()?;
+        let mut modifiers = 0;
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "shift" => modifier::SHIFT,
+                "ctrl" | "control" => modifier::CONTROL,
+                "alt" | "mod1" => modifier::ALT,
+                "super" | "mod4" => modifier::SUPER,
+                _ => return None,
+            };
+        }
+
+        // bindings match the unshifted key, so "ctrl+J" means "ctrl+shift+j"
+        let mut chars = key.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_uppercase() {
+                let lower: String = c.to_lowercase().collect();
+                let keysym = keysym_from_name(&lower)?;
+                return Some(KeyBinding::new(modifiers | modifier::SHIFT, keysym));
+            }
+        }
+
+        Some(KeyBinding::new(modifiers, keysym_from_name(key)?))
+    }
+}
+
+/// Everything a key can be bound to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    Select,
+    /// print the selection and keep moemenu open
+    SelectContinue,
+    Cancel,
+    Complete,
+    PreviousItem,
+    NextItem,
+    ItemAbove,
+    ItemBelow,
+    PreviousPage,
+    NextPage,
+    /// moves the cursor, or the selection once the cursor is at the start
+    Left,
+    /// moves the cursor, or the selection once the cursor is at the end
+    Right,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    DeleteBackward,
+    DeleteForward,
+    DeleteWord,
+    KillToEnd,
+    KillToStart,
+    PasteClipboard,
+    /// pastes the primary selection, the text that was last selected with the mouse
+    PastePrimary,
+    /// removes a default binding
+    Nothing,
+}
+
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("select", Action::Select),
+    ("select_continue", Action::SelectContinue),
+    ("cancel", Action::Cancel),
+    ("complete", Action::Complete),
+    ("previous_item", Action::PreviousItem),
+    ("next_item", Action::NextItem),
+    ("item_above", Action::ItemAbove),
+    ("item_below", Action::ItemBelow),
+    ("previous_page", Action::PreviousPage),
+    ("next_page", Action::NextPage),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("word_left", Action::WordLeft),
+    ("word_right", Action::WordRight),
+    ("line_start", Action::LineStart),
+    ("line_end", Action::LineEnd),
+    ("delete_backward", Action::DeleteBackward),
+    ("delete_forward", Action::DeleteForward),
+    ("delete_word", Action::DeleteWord),
+    ("kill_to_end", Action::KillToEnd),
+    ("kill_to_start", Action::KillToStart),
+    ("paste_clipboard", Action::PasteClipboard),
+    ("paste_primary", Action::PastePrimary),
+    ("none", Action::Nothing),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, action)| *action)
+    }
+}
+
+const DEFAULT_BINDINGS: &[(KeyBinding, Action)] = {
+    use keysym::*;
+    use modifier::*;
+    const fn key(keysym: Keysym) -> KeyBinding {
+        KeyBinding::new(0, keysym)
+    }
+    const fn ctrl(keysym: Keysym) -> KeyBinding {
+        KeyBinding::new(CONTROL, keysym)
+    }
+    const fn alt(keysym: Keysym) -> KeyBinding {
+        KeyBinding::new(ALT, keysym)
+    }
+    const fn shift(keysym: Keysym) -> KeyBinding {
+        KeyBinding::new(SHIFT, keysym)
+    }
+
+    &[
+        (key(Return), Action::Select),
+        (key(KP_Enter), Action::Select),
+        (ctrl(Return), Action::SelectContinue),
+        (key(Escape), Action::Cancel),
+        (key(Tab), Action::Complete),
+        (key(Left), Action::Left),
+        (key(Right), Action::Right),
+        (key(Up), Action::ItemAbove),
+        (key(Down), Action::ItemBelow),
+        (key(Prior), Action::PreviousPage),
+        (key(Next), Action::NextPage),
+        (key(Home), Action::LineStart),
+        (key(End), Action::LineEnd),
+        (key(BackSpace), Action::DeleteBackward),
+        (key(Delete), Action::DeleteForward),
+        (ctrl(Left), Action::WordLeft),
+        (ctrl(Right), Action::WordRight),
+        (ctrl(b'a' as Keysym), Action::LineStart),
+        (ctrl(b'e' as Keysym), Action::LineEnd),
+        (ctrl(b'w' as Keysym), Action::DeleteWord),
+        (ctrl(b'k' as Keysym), Action::KillToEnd),
+        (ctrl(b'u' as Keysym), Action::KillToStart),
+        (alt(b'b' as Keysym), Action::WordLeft),
+        (alt(b'f' as Keysym), Action::WordRight),
+        (ctrl(b'v' as Keysym), Action::PasteClipboard),
+        (
+            KeyBinding::new(CONTROL | SHIFT, b'v' as Keysym),
+            Action::PasteClipboard,
+        ),
+        (shift(Insert), Action::PastePrimary),
+    ]
+};
+
+/// The default key bindings with the ones of the config applied on top
+pub struct Bindings {
+    table: HashMap<KeyBinding, Action>,
+}
+
+impl Bindings {
+    pub fn new(overrides: &[(KeyBinding, Action)]) -> Self {
+        let mut table: HashMap<KeyBinding, Action> = DEFAULT_BINDINGS.iter().cloned().collect();
+        for (binding, action) in overrides {
+            match action {
+                Action::Nothing => table.remove(binding),
+                _ => table.insert(*binding, *action),
+            };
+        }
+
+        Bindings { table }
+    }
+
+    /// Finds the action for an unshifted keysym and the state of a key event.
+    pub fn get(&self, keysym: Keysym, state: u16) -> Option<Action> {
+        let binding = KeyBinding::new(state & modifier::RELEVANT, keysym);
+        self.table.get(&binding).cloned()
+    }
+}
+
+/// What happens to a group that is out of range for a key, see the XKB protocol spec
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GroupWrap {
+    Wrap,
+    Clamp,
+    Redirect(u8),
+}
+
+/// The symbols of a single key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySyms {
+    /// keysym of the first shift level of every group
+    pub groups: Vec<Keysym>,
+    pub wrap: GroupWrap,
+}
+
+impl KeySyms {
+    fn keysym(&self, group: u8) -> Keysym {
+        let count = self.groups.len();
+        if count == 0 {
+            return NO_SYMBOL;
+        }
+
+        let group = group as usize;
+        let group = match self.wrap {
+            _ if group < count => group,
+            GroupWrap::Wrap => group % count,
+            GroupWrap::Clamp => count - 1,
+            GroupWrap::Redirect(target) if (target as usize) < count => target as usize,
+            GroupWrap::Redirect(_) => 0,
+        };
+
+        self.groups[group]
+    }
+}
+
+pub const NO_SYMBOL: Keysym = 0;
+
+/// Translates keycodes into keysyms with the keyboard mapping of the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    min_keycode: Keycode,
+    keys: Vec<KeySyms>,
+}
+
+impl Keymap {
+    pub fn new(min_keycode: Keycode, keys: Vec<KeySyms>) -> Self {
+        Keymap { min_keycode, keys }
+    }
+
+    /// Builds the keymap from a core GetKeyboardMapping reply.
+    pub fn from_core(min_keycode: Keycode, keysyms_per_keycode: u8, keysyms: &[Keysym]) -> Self {
+        let width = (keysyms_per_keycode as usize).max(1);
+        let keys = keysyms
+            .chunks(width)
+            .map(|syms| {
+                // the core protocol stores two levels for each of at most two groups
+                let mut groups: Vec<Keysym> = syms.iter().step_by(2).take(2).cloned().collect();
+                if groups.len() > 1 && syms[2..].iter().take(2).all(|sym| *sym == NO_SYMBOL) {
+                    groups.truncate(1);
+                }
+                KeySyms {
+                    groups,
+                    wrap: GroupWrap::Wrap,
+                }
+            })
+            .collect();
+
+        Keymap::new(min_keycode, keys)
+    }
+
+    /// The keysym of the key in the group of the event state, without any modifiers applied.
+    pub fn keysym(&self, keycode: Keycode, state: u16) -> Keysym {
+        self.keysym_in_group(keycode, modifier::group(state))
+    }
+
+    pub fn keysym_in_group(&self, keycode: Keycode, group: u8) -> Keysym {
+        match keycode.checked_sub(self.min_keycode) {
+            Some(index) => self
+                .keys
+                .get(index as usize)
+                .map_or(NO_SYMBOL, |key| key.keysym(group)),
+            None => NO_SYMBOL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Keysym = b'a' as Keysym;
+    const CYRILLIC_EF: Keysym = 0x6c6;
+
+    /// a tiny keyboard starting at keycode 8 like most servers do:
+    /// Escape, a/A, Return and a key without symbols
+    fn core_keymap(keysyms_per_keycode: u8, keysyms: &[Keysym]) -> Keymap {
+        Keymap::from_core(8, keysyms_per_keycode, keysyms)
+    }
+
+    #[test]
+    fn core_keymap_resolves_the_first_level() {
+        #[rustfmt::skip]
+        let keymap = core_keymap(2, &[
+            keysym::Escape, NO_SYMBOL,
+            A, b'A' as Keysym,
+            keysym::Return, NO_SYMBOL,
+            NO_SYMBOL, NO_SYMBOL,
+        ]);
+
+        assert_eq!(keymap.keysym(8, 0), keysym::Escape);
+        assert_eq!(keymap.keysym(9, modifier::SHIFT), A);
+        assert_eq!(keymap.keysym(10, modifier::CONTROL), keysym::Return);
+        assert_eq!(keymap.keysym(11, 0), NO_SYMBOL);
+    }
+
+    #[test]
+    fn keycodes_outside_of_the_keymap_have_no_symbol() {
+        let keymap = core_keymap(1, &[keysym::Escape]);
+        assert_eq!(keymap.keysym(7, 0), NO_SYMBOL);
+        assert_eq!(keymap.keysym(9, 0), NO_SYMBOL);
+    }
+
+    #[test]
+    fn core_keymap_uses_the_second_group() {
+        // us,ru layout where the second group is active through XKB
+        #[rustfmt::skip]
+        let keymap = core_keymap(4, &[
+            A, b'A' as Keysym, CYRILLIC_EF, 0x6e6,
+            keysym::Escape, NO_SYMBOL, NO_SYMBOL, NO_SYMBOL,
+        ]);
+        let second_group = 1 << 13;
+
+        assert_eq!(keymap.keysym(8, 0), A);
+        assert_eq!(keymap.keysym(8, second_group), CYRILLIC_EF);
+        // keys that are the same in every group only have one
+        assert_eq!(keymap.keysym(9, second_group), keysym::Escape);
+    }
+
+    #[test]
+    fn out_of_range_groups_follow_the_key_setting() {
+        let key = |wrap| KeySyms {
+            groups: vec![A, CYRILLIC_EF],
+            wrap,
+        };
+        let keymap = Keymap::new(
+            8,
+            vec![
+                key(GroupWrap::Wrap),
+                key(GroupWrap::Clamp),
+                key(GroupWrap::Redirect(0)),
+            ],
+        );
+
+        assert_eq!(keymap.keysym_in_group(8, 3), CYRILLIC_EF);
+        assert_eq!(keymap.keysym_in_group(9, 3), CYRILLIC_EF);
+        assert_eq!(keymap.keysym_in_group(10, 3), A);
+        assert_eq!(keymap.keysym_in_group(10, 2), A);
+    }
+
+    #[test]
+    fn bindings_match_keysyms_from_the_keymap() {
+        // keycodes of a non-evdev server, nothing like the usual 9 and 36
+        #[rustfmt::skip]
+        let keymap = core_keymap(2, &[
+            keysym::Return, NO_SYMBOL,
+            keysym::Escape, NO_SYMBOL,
+            b'j' as Keysym, b'J' as Keysym,
+        ]);
+        let bindings = Bindings::new(&[(KeyBinding::parse("ctrl+j").unwrap(), Action::Select)]);
+        let action = |keycode, state| bindings.get(keymap.keysym(keycode, state), state);
+
+        assert_eq!(action(8, 0), Some(Action::Select));
+        assert_eq!(action(8, modifier::CONTROL), Some(Action::SelectContinue));
+        // num lock does not get in the way
+        assert_eq!(action(9, 0x10), Some(Action::Cancel));
+        assert_eq!(action(10, modifier::CONTROL), Some(Action::Select));
+        assert_eq!(action(10, 0), None);
+    }
+}
@@ -15,24 +15,51 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+mod cli;
 mod config;
 mod defaults;
 mod draw;
+mod keys;
 mod menu;
+mod resources;
 mod search;
 mod xorg;
 
+use std::env;
 use std::io;
 use std::io::BufRead;
+use std::ops::Range;
 use std::process::exit;
 
+use crate::cli::Command;
 pub use crate::config::Config;
 pub use crate::menu::Menu;
-use crate::search::ContainsEngine;
-use crate::xorg::XorgUserInterface;
+use crate::xorg::{XorgConnection, XorgUserInterface};
+
+/// An item that matched the search term
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub item: String,
+    /// byte ranges of the item that matched the search term
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Match {
+    pub fn new(item: &str, spans: Vec<Range<usize>>) -> Self {
+        Match {
+            item: item.to_string(),
+            spans,
+        }
+    }
+}
 
 pub trait SearchEngine {
-    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<String>;
+    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match>;
+
+    /// A problem with the current search term that should be shown to the user
+    fn error(&self) -> Option<String> {
+        None
+    }
 }
 
 pub trait UserInterface {
@@ -66,9 +93,27 @@ fn run_ui(ui: &mut dyn UserInterface, menu: &mut Menu) {
 }
 
 fn main() {
-    let config = Config::get();
+    // -v and usage errors work without a display, there are no X resources then
+    let xorg = XorgConnection::open();
+    let resources = xorg.as_ref().map_or("", XorgConnection::resources);
+    let mut config = Config::get(resources);
+    match cli::parse_args(env::args().skip(1), &mut config) {
+        Ok(Command::Run) => {}
+        Ok(Command::PrintVersion) => {
+            println!("moemenu-{}", env!("CARGO_PKG_VERSION"));
+            exit(0);
+        }
+        Err(e) => {
+            eprintln!("moemenu: {}", e);
+            eprintln!("{}", cli::USAGE);
+            exit(1);
+        }
+    }
+
     let input = read_stdin();
-    let mut menu = Menu::new(Box::from(ContainsEngine::new()), input);
-    let mut ui = XorgUserInterface::new(config).unwrap();
+    let mut menu = Menu::new(search::create_engine(&config), input);
+    let mut ui = xorg
+        .and_then(|xorg| XorgUserInterface::new(xorg, config))
+        .unwrap();
     run_ui(&mut ui, &mut menu);
 }
@@ -15,15 +15,26 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+use std::ops::Range;
+
 use crate::SearchEngine;
 
 /// Manages the whole state of the program
 pub struct Menu {
     input: Vec<String>,
     search_term: String,
+    /// byte offset of the text cursor in the search term
+    cursor: usize,
+    /// text an input method is still composing, shown at the cursor
+    preedit: String,
+    /// byte offset of the cursor in the preedit text
+    preedit_cursor: usize,
     items: Vec<String>,
+    spans: Vec<Vec<Range<usize>>>,
     selection: usize,
     shift: usize,
+    page_end: usize,
+    previous_page_start: usize,
     engine: Box<dyn SearchEngine>,
 }
 
@@ -31,28 +42,174 @@ impl Menu {
     pub fn new(engine: Box<dyn SearchEngine>, input: Vec<String>) -> Self {
         return Menu {
             search_term: String::from(""),
+            cursor: 0,
+            preedit: String::new(),
+            preedit_cursor: 0,
             input: input.clone(),
             items: input.clone(),
+            spans: vec![Vec::new(); input.len()],
             selection: 0,
             shift: 0,
+            page_end: 0,
+            previous_page_start: 0,
             engine,
         };
     }
 
     pub fn search(&mut self, search_term: String) {
+        self.cursor = search_term.len();
+        self.search_term = search_term;
+        self.update_search();
+    }
+
+    fn update_search(&mut self) {
         self.selection = 0;
         self.shift = 0;
-        self.search_term = search_term;
-        self.items = self.engine.search(&self.search_term, &self.input);
+        let matches = self.engine.search(&self.search_term, &self.input);
+        self.spans = matches.iter().map(|m| m.spans.clone()).collect();
+        self.items = matches.into_iter().map(|m| m.item).collect();
+    }
+
+    /// Removes the text between the two byte offsets and puts the cursor there.
+    fn delete_range(&mut self, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+
+        self.search_term.replace_range(start..end, "");
+        self.cursor = start;
+        self.update_search();
+    }
+
+    /// byte offset of the char in front of the cursor
+    fn previous_char_boundary(&self) -> usize {
+        match self.search_term[..self.cursor].chars().next_back() {
+            Some(c) => self.cursor - c.len_utf8(),
+            None => 0,
+        }
+    }
+
+    /// byte offset of the char after the cursor
+    fn next_char_boundary(&self) -> usize {
+        match self.search_term[self.cursor..].chars().next() {
+            Some(c) => self.cursor + c.len_utf8(),
+            None => self.cursor,
+        }
+    }
+
+    /// start of the word in front of the cursor, skipping whitespace like dmenu does
+    fn previous_word_boundary(&self) -> usize {
+        let before = self.search_term[..self.cursor].trim_end();
+        match before.rfind(char::is_whitespace) {
+            Some(i) => i + before[i..].chars().next().unwrap().len_utf8(),
+            None => 0,
+        }
+    }
+
+    /// end of the word after the cursor
+    fn next_word_boundary(&self) -> usize {
+        let after = &self.search_term[self.cursor..];
+        let skipped = after.len() - after.trim_start().len();
+        match after[skipped..].find(char::is_whitespace) {
+            Some(i) => self.cursor + skipped + i,
+            None => self.search_term.len(),
+        }
     }
 
     pub fn input_char(&mut self, c: char) {
-        self.search(format!("{}{}", self.search_term, c));
+        self.search_term.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+        self.update_search();
     }
 
+    /// Inserts the first line of the text at the cursor.
+    pub fn paste(&mut self, text: &str) {
+        let line: String = text
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect();
+        if line.is_empty() {
+            return;
+        }
+
+        self.search_term.insert_str(self.cursor, &line);
+        self.cursor += line.len();
+        self.update_search();
+    }
+
+    /// Deletes the char in front of the cursor.
     pub fn delete_char(&mut self) {
-        self.search_term.pop();
-        self.search(self.search_term.clone());
+        self.delete_range(self.previous_char_boundary(), self.cursor);
+    }
+
+    /// Deletes the char after the cursor.
+    pub fn delete_next_char(&mut self) {
+        self.delete_range(self.cursor, self.next_char_boundary());
+    }
+
+    pub fn delete_word(&mut self) {
+        self.delete_range(self.previous_word_boundary(), self.cursor);
+    }
+
+    pub fn kill_to_end(&mut self) {
+        self.delete_range(self.cursor, self.search_term.len());
+    }
+
+    pub fn kill_to_start(&mut self) {
+        self.delete_range(0, self.cursor);
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Takes the text an input method is composing and the char index of its cursor.
+    pub fn set_preedit(&mut self, preedit: String, cursor: usize) {
+        self.preedit_cursor = preedit
+            .char_indices()
+            .nth(cursor)
+            .map_or(preedit.len(), |(i, _)| i);
+        self.preedit = preedit;
+    }
+
+    /// the preedit text and the byte offset of its cursor
+    pub fn get_preedit(&self) -> (&str, usize) {
+        (&self.preedit, self.preedit_cursor)
+    }
+
+    pub fn cursor_at_start(&self) -> bool {
+        self.cursor == 0
+    }
+
+    pub fn cursor_at_end(&self) -> bool {
+        self.cursor == self.search_term.len()
+    }
+
+    pub fn move_cursor_left(&mut self) {
+        self.cursor = self.previous_char_boundary();
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        self.cursor = self.next_char_boundary();
+    }
+
+    pub fn move_cursor_word_left(&mut self) {
+        self.cursor = self.previous_word_boundary();
+    }
+
+    pub fn move_cursor_word_right(&mut self) {
+        self.cursor = self.next_word_boundary();
+    }
+
+    pub fn move_cursor_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_cursor_to_end(&mut self) {
+        self.cursor = self.search_term.len();
     }
 
     pub fn complete(&mut self) {
@@ -65,26 +222,59 @@ impl Menu {
         return self.search_term.clone();
     }
 
+    pub fn get_search_error(&self) -> Option<String> {
+        self.engine.error()
+    }
+
     pub fn get_items(&self) -> &Vec<String> {
         return &self.items;
     }
 
+    /// byte ranges of the item at index that matched the search term
+    pub fn get_match_spans(&self, index: usize) -> &[Range<usize>] {
+        match self.spans.get(index) {
+            Some(spans) => spans,
+            None => &[],
+        }
+    }
+
     pub fn get_selection(&self) -> usize {
         return self.selection;
     }
 
     pub fn select_next_item(&mut self) {
-        if self.selection < self.items.len() - 1 {
+        if self.selection + 1 < self.items.len() {
             self.selection += 1;
         }
     }
 
+    /// Selects the item with that index, if there is one.
+    pub fn select_item(&mut self, index: usize) {
+        if index < self.items.len() {
+            self.selection = index;
+        }
+    }
+
     pub fn select_previous_item(&mut self) {
         if self.selection > 0 {
             self.selection -= 1;
         }
     }
 
+    pub fn select_next_page(&mut self) {
+        if !self.items.is_empty() {
+            self.selection = (self.page_end + 1).min(self.items.len() - 1);
+        }
+    }
+
+    pub fn select_previous_page(&mut self) {
+        if self.shift > 0 {
+            self.selection = self.previous_page_start;
+        } else {
+            self.selection = 0;
+        }
+    }
+
     pub fn get_selected_item(&self) -> Option<String> {
         match self.items.get(self.selection as usize) {
             Some(s) => Some(s.clone()),
@@ -96,7 +286,44 @@ impl Menu {
         return self.shift;
     }
 
-    pub fn update_page(&mut self, last_first_item: usize, current_last_item: usize) -> bool {
+    /// Moves the selection one row up in a grid with the given number of columns.
+    pub fn select_item_above(&mut self, columns: usize) {
+        if self.selection >= columns {
+            self.selection -= columns;
+        }
+    }
+
+    /// Moves the selection one row down in a grid with the given number of columns.
+    pub fn select_item_below(&mut self, columns: usize) {
+        let len = self.items.len();
+        if len == 0 {
+            return;
+        }
+
+        // jump to the last item if the row below is not full
+        if self.selection / columns < (len - 1) / columns {
+            self.selection = (self.selection + columns).min(len - 1);
+        }
+    }
+
+    /// Moves the page so the selection is visible, page_size is known for layouts where every
+    /// page holds the same number of items.
+    pub fn update_page(
+        &mut self,
+        last_first_item: usize,
+        current_last_item: usize,
+        page_size: Option<usize>,
+    ) -> bool {
+        self.page_end = current_last_item;
+        self.previous_page_start = last_first_item;
+
+        if let Some(size) = page_size {
+            let shift = self.selection / size.max(1) * size.max(1);
+            let changed = shift != self.shift;
+            self.shift = shift;
+            return changed;
+        }
+
         if self.selection > current_last_item {
             self.shift = current_last_item as usize + 1;
             return true;
@@ -111,3 +338,126 @@ impl Menu {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CaseMode;
+    use crate::search::ContainsEngine;
+
+    fn menu_with_term(term: &str) -> Menu {
+        let input = ["héllo wörld", "hllo", "foo bar", "foo"];
+        let engine = Box::from(ContainsEngine::new(CaseMode::Sensitive));
+        let mut menu = Menu::new(engine, input.iter().map(|s| s.to_string()).collect());
+        menu.search(term.to_string());
+        menu
+    }
+
+    #[test]
+    fn moves_over_multibyte_chars() {
+        let mut menu = menu_with_term("héllo");
+        assert_eq!(menu.get_cursor(), 6);
+
+        menu.move_cursor_to_start();
+        menu.move_cursor_right();
+        menu.move_cursor_right();
+        assert_eq!(menu.get_cursor(), 3);
+        menu.move_cursor_left();
+        assert_eq!(menu.get_cursor(), 1);
+        menu.move_cursor_left();
+        menu.move_cursor_left();
+        assert_eq!(menu.get_cursor(), 0);
+
+        menu.move_cursor_right();
+        menu.delete_next_char();
+        assert_eq!(menu.get_search_term(), "hllo");
+        assert_eq!(menu.get_cursor(), 1);
+        assert_eq!(menu.get_items(), &vec!["hllo"]);
+
+        menu.move_cursor_to_end();
+        menu.delete_char();
+        menu.move_cursor_to_start();
+        menu.delete_next_char();
+        assert_eq!(menu.get_search_term(), "ll");
+        assert_eq!(menu.get_items(), &vec!["héllo wörld", "hllo"]);
+    }
+
+    #[test]
+    fn moves_by_words_over_spaces_and_punctuation() {
+        // like in dmenu only whitespace separates words
+        let mut menu = menu_with_term("foo,  bar.baz  qux");
+
+        menu.move_cursor_word_left();
+        assert_eq!(menu.get_cursor(), 15);
+        menu.move_cursor_word_left();
+        assert_eq!(menu.get_cursor(), 6);
+        menu.move_cursor_word_left();
+        assert_eq!(menu.get_cursor(), 0);
+        menu.move_cursor_word_left();
+        assert_eq!(menu.get_cursor(), 0);
+
+        menu.move_cursor_word_right();
+        assert_eq!(menu.get_cursor(), 4);
+        menu.move_cursor_word_right();
+        assert_eq!(menu.get_cursor(), 13);
+        menu.move_cursor_word_right();
+        assert_eq!(menu.get_cursor(), 18);
+        menu.move_cursor_word_right();
+        assert_eq!(menu.get_cursor(), 18);
+    }
+
+    #[test]
+    fn deletes_words_with_multibyte_chars_and_trailing_spaces() {
+        let mut menu = menu_with_term("héllo wörld  ");
+        assert!(menu.get_items().is_empty());
+
+        menu.delete_word();
+        assert_eq!(menu.get_search_term(), "héllo ");
+        assert_eq!(menu.get_cursor(), 7);
+        assert_eq!(menu.get_items(), &vec!["héllo wörld"]);
+
+        menu.delete_word();
+        assert_eq!(menu.get_search_term(), "");
+        assert_eq!(menu.get_cursor(), 0);
+        assert_eq!(menu.get_items().len(), 4);
+    }
+
+    #[test]
+    fn kills_to_the_end_and_to_the_start() {
+        let mut menu = menu_with_term("foo bar");
+        menu.move_cursor_to_start();
+        menu.move_cursor_word_right();
+
+        menu.kill_to_end();
+        assert_eq!(menu.get_search_term(), "foo");
+        assert_eq!(menu.get_cursor(), 3);
+        assert_eq!(menu.get_items(), &vec!["foo bar", "foo"]);
+
+        let mut menu = menu_with_term("foo bar");
+        menu.move_cursor_word_left();
+        menu.move_cursor_left();
+
+        menu.kill_to_start();
+        assert_eq!(menu.get_search_term(), " bar");
+        assert_eq!(menu.get_cursor(), 0);
+        assert_eq!(menu.get_items(), &vec!["foo bar"]);
+    }
+
+    #[test]
+    fn inserts_in_the_middle_of_the_term() {
+        let mut menu = menu_with_term("hllo");
+        menu.move_cursor_to_start();
+        menu.move_cursor_right();
+
+        menu.input_char('é');
+        assert_eq!(menu.get_search_term(), "héllo");
+        assert_eq!(menu.get_cursor(), 3);
+        assert_eq!(menu.get_items(), &vec!["héllo wörld"]);
+
+        menu.move_cursor_to_start();
+        menu.paste("fo\nignored");
+        assert_eq!(menu.get_search_term(), "fohéllo");
+        assert_eq!(menu.get_cursor(), 2);
+        assert!(menu.get_items().is_empty());
+    }
+}
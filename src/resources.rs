@@ -0,0 +1,156 @@
+/*
+ * This file is part of moemenu.
+ * Copyright (C) 2021 fence.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+use crate::cli::apply_font;
+use crate::config::{parse_color, Config};
+
+/// Looks up a resource in the RESOURCE_MANAGER property xrdb fills, it has one "name:\tvalue" per
+/// line.
+pub fn get<'a>(resources: &'a str, name: &str) -> Option<&'a str> {
+    resources.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() == name {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+/// Looks up a resource meant only for moemenu, "moemenu.name" wins over "moemenu*name".
+pub fn lookup_own<'a>(resources: &'a str, name: &str) -> Option<&'a str> {
+    get(resources, &format!("moemenu.{}", name))
+        .or_else(|| get(resources, &format!("moemenu*{}", name)))
+}
+
+/// Looks up a resource of moemenu and falls back to the "*.name" or "*name" a theme shares with
+/// other programs.
+pub fn lookup<'a>(resources: &'a str, name: &str) -> Option<&'a str> {
+    lookup_own(resources, name)
+        .or_else(|| get(resources, &format!("*.{}", name)))
+        .or_else(|| get(resources, &format!("*{}", name)))
+}
+
+/// Applies moemenu.font, moemenu.background and friends to the config, invalid values are skipped.
+pub fn apply(resources: &str, config: &mut Config) {
+    let get = |name: &str| lookup(resources, name);
+
+    // the shared font is usually in the xft: or XLFD form of terminals, Pango can not use it
+    if let Some(font) = lookup_own(resources, "font") {
+        apply_font(font, config);
+    }
+    if let Some(prompt) = get("prompt") {
+        config.prompt = Some(prompt.to_string());
+    }
+
+    let colors = &mut config.colors;
+    let color_resources = [
+        ("background", &mut colors.background),
+        ("foreground", &mut colors.font),
+        ("selectedBackground", &mut colors.selected_background),
+        ("selectedForeground", &mut colors.selected_font),
+        ("matchBackground", &mut colors.match_background),
+        ("matchForeground", &mut colors.match_font),
+        ("promptBackground", &mut colors.prompt_background),
+        ("promptForeground", &mut colors.prompt_font),
+        ("borderColor", &mut colors.border),
+    ];
+    for (name, color) in color_resources {
+        if let Some(value) = get(name).and_then(parse_color) {
+            *color = value;
+        }
+    }
+
+    let size_resources = [
+        ("height", &mut config.height),
+        ("lines", &mut config.lines),
+        ("columns", &mut config.columns),
+        ("borderWidth", &mut config.border_width),
+        ("padding", &mut config.padding),
+    ];
+    for (name, size) in size_resources {
+        if let Some(Ok(value)) = get(name).map(str::parse::<u16>) {
+            *size = value;
+        }
+    }
+
+    if let Some(Ok(radius)) = get("cornerRadius").map(str::parse::<f64>) {
+        config.corner_radius = radius;
+    }
+    if let Some(Ok(scale)) = get("scale").map(str::parse::<f64>) {
+        if scale > 0.0 {
+            config.scale = Some(scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defaults::DEFAULT_CONFIG;
+    use rgb::RGBA8;
+
+    const RESOURCES: &str = "Xft.dpi:\t192\n\
+                             *.background:\t#222222\n\
+                             *foreground:\t#bbbbbb\n\
+                             moemenu*background:\t#333333\n\
+                             moemenu.selectedBackground:\t#005577\n\
+                             moemenu.font:\tIosevka 11\n\
+                             moemenu.lines:\tmany\n";
+
+    #[test]
+    fn gets_exact_names() {
+        assert_eq!(get(RESOURCES, "Xft.dpi"), Some("192"));
+        assert_eq!(get(RESOURCES, "Xft"), None);
+    }
+
+    #[test]
+    fn prefers_moemenu_resources() {
+        assert_eq!(lookup(RESOURCES, "background"), Some("#333333"));
+        assert_eq!(lookup(RESOURCES, "foreground"), Some("#bbbbbb"));
+        assert_eq!(lookup(RESOURCES, "prompt"), None);
+    }
+
+    #[test]
+    fn ignores_the_font_of_other_programs() {
+        let mut config = DEFAULT_CONFIG;
+        apply(
+            "*.font:\txft:Monospace:pixelsize=14\n*font:\t-misc-fixed-*-*-*-*-13-*-*-*-*-*-*-*\n",
+            &mut config,
+        );
+        assert_eq!(config.font, DEFAULT_CONFIG.font);
+        assert_eq!(config.font_size, DEFAULT_CONFIG.font_size);
+
+        apply("moemenu*font:\tmonospace:pixelsize=14\n", &mut config);
+        assert_eq!(config.font.as_deref(), Some("monospace"));
+        assert_eq!(config.font_size, 14.0);
+    }
+
+    #[test]
+    fn applies_to_config() {
+        let mut config = DEFAULT_CONFIG;
+        apply(RESOURCES, &mut config);
+        assert_eq!(config.colors.background, RGBA8::new(51, 51, 51, 255));
+        assert_eq!(config.colors.font, RGBA8::new(187, 187, 187, 255));
+        assert_eq!(
+            config.colors.selected_background,
+            RGBA8::new(0, 85, 119, 255)
+        );
+        assert_eq!(config.font.as_deref(), Some("Iosevka 11"));
+        assert_eq!(config.lines, DEFAULT_CONFIG.lines);
+    }
+}
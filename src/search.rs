@@ -15,23 +15,768 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
-use crate::SearchEngine;
+use std::cmp::Reverse;
+use std::ops::Range;
+
+use crate::config::{CaseMode, Config, EngineKind};
+use crate::{Match, SearchEngine};
+
+pub fn create_engine(config: &Config) -> Box<dyn SearchEngine> {
+    match config.search_engine {
+        EngineKind::Contains => Box::from(ContainsEngine::new(config.case)),
+        EngineKind::Fuzzy => Box::from(FuzzyEngine::new(config.case)),
+        EngineKind::Prefix => Box::from(PrefixEngine::new(config.case)),
+        EngineKind::Tokens => Box::from(TokenEngine::new(config.case)),
+        #[cfg(feature = "regex")]
+        EngineKind::Regex => Box::from(regex_engine::RegexEngine::new(config.case)),
+    }
+}
+
+/// Decides if a search for the needle should care about case.
+fn is_case_sensitive(case: CaseMode, needle: &str) -> bool {
+    match case {
+        CaseMode::Sensitive => true,
+        CaseMode::Insensitive => false,
+        CaseMode::Smart => needle.chars().any(char::is_uppercase),
+    }
+}
+
+/// Simple unicode case folding, one char always folds into exactly one char so match positions
+/// map straight back to the item. Full folding like ß to ss is not done.
+fn fold_char(c: char) -> char {
+    match c {
+        // folded to their non-final/non-archaic forms, to_lowercase keeps them
+        'ς' => 'σ',
+        'ſ' => 's',
+        _ => {
+            let mut lower = c.to_lowercase();
+            match (lower.next(), lower.next()) {
+                (Some(l), None) => l,
+                // chars like İ only have a multi char lowercase form
+                _ => c,
+            }
+        }
+    }
+}
+
+fn fold_case(s: &str) -> String {
+    s.chars().map(fold_char).collect()
+}
+
+/// Folds the string unless the search is case sensitive.
+fn prepare(s: &str, case_sensitive: bool) -> String {
+    match case_sensitive {
+        true => s.to_string(),
+        false => fold_case(s),
+    }
+}
+
+/// Maps a byte range of the prepared string back onto the original string.
+fn original_span(original: &str, prepared: &str, range: Range<usize>) -> Range<usize> {
+    // folding keeps the char count, but not necessarily the byte count
+    let start = prepared[..range.start].chars().count();
+    let len = prepared[range].chars().count();
+    let byte_offset = |n: usize| match original.char_indices().nth(n) {
+        Some((offset, _)) => offset,
+        None => original.len(),
+    };
+
+    byte_offset(start)..byte_offset(start + len)
+}
+
+/// Span of the first occurrence of the prepared needle in the item.
+fn find_span(item: &str, prepared: &str, needle: &str) -> Option<Range<usize>> {
+    prepared
+        .find(needle)
+        .map(|start| original_span(item, prepared, start..start + needle.len()))
+}
 
 // very simple engine based on String::contains
-pub struct ContainsEngine;
+pub struct ContainsEngine {
+    case: CaseMode,
+}
 
 impl ContainsEngine {
-    pub fn new() -> Self {
-        ContainsEngine {}
+    pub fn new(case: CaseMode) -> Self {
+        ContainsEngine { case }
     }
 }
 
 impl SearchEngine for ContainsEngine {
-    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<String> {
+    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match> {
+        let case_sensitive = is_case_sensitive(self.case, needle);
+        let needle = prepare(needle, case_sensitive);
+
         haystack
-            .clone()
-            .into_iter()
-            .filter(|x| x.contains(needle))
+            .iter()
+            .filter_map(|x| {
+                let span = find_span(x, &prepare(x, case_sensitive), &needle)?;
+                Some(Match::new(x, vec![span]))
+            })
             .collect()
     }
 }
+
+// ranks like dmenu: exact matches first, then prefix matches, then substring matches
+pub struct PrefixEngine {
+    case: CaseMode,
+}
+
+impl PrefixEngine {
+    pub fn new(case: CaseMode) -> Self {
+        PrefixEngine { case }
+    }
+}
+
+impl SearchEngine for PrefixEngine {
+    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match> {
+        let case_sensitive = is_case_sensitive(self.case, needle);
+        let needle = prepare(needle, case_sensitive);
+
+        let mut exact = Vec::new();
+        let mut prefix = Vec::new();
+        let mut substring = Vec::new();
+        for item in haystack {
+            let folded = prepare(item, case_sensitive);
+            let span = match find_span(item, &folded, &needle) {
+                Some(span) => span,
+                None => continue,
+            };
+
+            let found = Match::new(item, vec![span]);
+            if folded == needle {
+                exact.push(found);
+            } else if folded.starts_with(&needle) {
+                prefix.push(found);
+            } else {
+                substring.push(found);
+            }
+        }
+
+        exact.append(&mut prefix);
+        exact.append(&mut substring);
+        exact
+    }
+}
+
+/// A single whitespace separated part of the search term
+struct Token {
+    text: String,
+    negated: bool,
+    prefix: bool,
+    suffix: bool,
+}
+
+impl Token {
+    /// Parses `!token`, `^prefix` and `suffix$`, returns None for tokens without any text.
+    fn parse(raw: &str) -> Option<Self> {
+        let (negated, raw) = match raw.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        let (prefix, raw) = match raw.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+        let (suffix, raw) = match raw.strip_suffix('$') {
+            Some(rest) => (true, rest),
+            None => (false, raw),
+        };
+
+        if raw.is_empty() {
+            return None;
+        }
+
+        Some(Token {
+            text: raw.to_string(),
+            negated,
+            prefix,
+            suffix,
+        })
+    }
+
+    /// Byte range of the prepared item the token was found at.
+    fn find(&self, item: &str) -> Option<Range<usize>> {
+        let len = self.text.len();
+        match (self.prefix, self.suffix) {
+            (true, true) if item == self.text => Some(0..len),
+            (true, false) if item.starts_with(&self.text) => Some(0..len),
+            (false, true) if item.ends_with(&self.text) => Some(item.len() - len..item.len()),
+            (false, false) => item.find(&self.text).map(|start| start..start + len),
+            _ => None,
+        }
+    }
+}
+
+// splits the search term on whitespace, every token has to match
+pub struct TokenEngine {
+    case: CaseMode,
+}
+
+impl TokenEngine {
+    pub fn new(case: CaseMode) -> Self {
+        TokenEngine { case }
+    }
+}
+
+impl SearchEngine for TokenEngine {
+    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match> {
+        let case_sensitive = is_case_sensitive(self.case, needle);
+        let tokens: Vec<Token> = prepare(needle, case_sensitive)
+            .split_whitespace()
+            .filter_map(Token::parse)
+            .collect();
+
+        haystack
+            .iter()
+            .filter_map(|item| {
+                let prepared = prepare(item, case_sensitive);
+                let mut spans = Vec::new();
+                for token in &tokens {
+                    match (token.find(&prepared), token.negated) {
+                        (Some(range), false) => spans.push(original_span(item, &prepared, range)),
+                        (None, true) => {}
+                        _ => return None,
+                    }
+                }
+                spans.sort_by_key(|span| span.start);
+                Some(Match::new(item, spans))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "regex")]
+mod regex_engine {
+    use regex::{Regex, RegexBuilder};
+
+    use super::is_case_sensitive;
+    use crate::config::CaseMode;
+    use crate::{Match, SearchEngine};
+
+    // treats the search term as a regular expression
+    pub struct RegexEngine {
+        case: CaseMode,
+        pattern: Option<(String, Regex)>,
+        last_result: Option<Vec<Match>>,
+        error: Option<String>,
+    }
+
+    impl RegexEngine {
+        pub fn new(case: CaseMode) -> Self {
+            RegexEngine {
+                case,
+                pattern: None,
+                last_result: None,
+                error: None,
+            }
+        }
+
+        /// Compiles the needle unless it is the pattern that was used last time.
+        fn compile(&mut self, needle: &str) -> Result<&Regex, regex::Error> {
+            let is_cached = match &self.pattern {
+                Some((pattern, _)) => pattern == needle,
+                None => false,
+            };
+
+            if !is_cached {
+                let case_sensitive = match self.case {
+                    CaseMode::Smart => has_uppercase(needle),
+                    case => is_case_sensitive(case, needle),
+                };
+                let regex = RegexBuilder::new(needle)
+                    .case_insensitive(!case_sensitive)
+                    .build()?;
+                self.pattern = Some((needle.to_string(), regex));
+            }
+
+            Ok(&self.pattern.as_ref().unwrap().1)
+        }
+    }
+
+    /// Smart case for a pattern, uppercase letters of escapes like \S or \p{Lu} and of group
+    /// names do not count.
+    fn has_uppercase(pattern: &str) -> bool {
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    let escaped = chars.next();
+                    if chars.peek() == Some(&'{') {
+                        // \p{Lu}, \x{1F600} and friends
+                        chars.by_ref().find(|&c| c == '}');
+                    } else if matches!(escaped, Some('p') | Some('P')) {
+                        // one letter classes like \pL
+                        chars.next();
+                    }
+                }
+                '(' if chars.peek() == Some(&'?') => {
+                    chars.next();
+                    if chars.peek() == Some(&'P') {
+                        chars.next();
+                    }
+                    if chars.peek() == Some(&'<') {
+                        chars.by_ref().find(|&c| c == '>');
+                    }
+                }
+                c if c.is_uppercase() => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    impl SearchEngine for RegexEngine {
+        fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match> {
+            match self.compile(needle) {
+                Ok(regex) => {
+                    let result: Vec<Match> = haystack
+                        .iter()
+                        .filter(|item| regex.is_match(item))
+                        .map(|item| {
+                            let spans = regex
+                                .find_iter(item)
+                                .filter(|m| !m.range().is_empty())
+                                .map(|m| m.range())
+                                .collect();
+                            Match::new(item, spans)
+                        })
+                        .collect();
+                    self.error = None;
+                    self.last_result = Some(result.clone());
+                    result
+                }
+                // most likely the pattern is not finished yet, keep what we had
+                Err(e) => {
+                    // the last line of syntax errors contains the actual message
+                    let message = e.to_string();
+                    self.error = message.lines().last().map(|l| l.trim().to_string());
+                    match &self.last_result {
+                        Some(result) => result.clone(),
+                        None => haystack.iter().map(|i| Match::new(i, Vec::new())).collect(),
+                    }
+                }
+            }
+        }
+
+        fn error(&self) -> Option<String> {
+            self.error.clone()
+        }
+    }
+}
+
+// scores used by the FuzzyEngine, loosely modeled after fzy/fzf
+// opening a gap costs less than a bonus, so skipping a single separator to land on a word start is
+// still net positive, but every further skipped char costs more than any bonus, so a dense match
+// beats scattered word starts
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 6;
+const BONUS_BOUNDARY: i64 = 6;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 9;
+const PENALTY_LEADING: i64 = 1;
+const MAX_LEADING_PENALTY: i64 = 6;
+
+// matches the needle as a subsequence and sorts the results by score
+pub struct FuzzyEngine {
+    case: CaseMode,
+}
+
+impl FuzzyEngine {
+    pub fn new(case: CaseMode) -> Self {
+        FuzzyEngine { case }
+    }
+
+    /// Returns the score and char positions of the best alignment or None if the needle is not a
+    /// subsequence.
+    fn score(
+        &self,
+        needle: &[char],
+        haystack: &[char],
+        case_sensitive: bool,
+    ) -> Option<(i64, Vec<usize>)> {
+        let (n, m) = (needle.len(), haystack.len());
+        if n > m {
+            return None;
+        }
+
+        let bonus: Vec<i64> = (0..m).map(|j| boundary_bonus(haystack, j)).collect();
+
+        // best score with needle[i] matched at haystack[j]
+        let mut rows: Vec<Vec<Option<i64>>> = Vec::with_capacity(n);
+        let empty = vec![None; m];
+        for (i, &nc) in needle.iter().enumerate() {
+            let previous = rows.last().unwrap_or(&empty);
+            let mut current: Vec<Option<i64>> = vec![None; m];
+            // best score of the previous row that would leave a gap before j
+            let mut gapped: Option<i64> = None;
+
+            for j in 0..m {
+                if j >= 2 {
+                    let opened = previous[j - 2].map(|s| s - PENALTY_GAP_START);
+                    let extended = gapped.map(|s| s - PENALTY_GAP_EXTENSION);
+                    gapped = opened.max(extended);
+                }
+
+                let hc = match case_sensitive {
+                    true => haystack[j],
+                    false => fold_char(haystack[j]),
+                };
+                if nc != hc {
+                    continue;
+                }
+
+                let base = SCORE_MATCH + bonus[j];
+                current[j] = if i == 0 {
+                    let leading = (j as i64).min(MAX_LEADING_PENALTY) * PENALTY_LEADING;
+                    Some(base - leading)
+                } else {
+                    let consecutive = match j {
+                        0 => None,
+                        _ => previous[j - 1].map(|s| s + BONUS_CONSECUTIVE),
+                    };
+                    consecutive.max(gapped).map(|s| s + base)
+                };
+            }
+
+            rows.push(current);
+        }
+
+        let (mut j, score) = rows[n - 1]
+            .iter()
+            .enumerate()
+            .filter_map(|(j, score)| score.map(|s| (j, s)))
+            .max_by_key(|&(j, score)| (score, Reverse(j)))?;
+
+        // walk back through the rows to find out where the best alignment came from
+        let mut positions = vec![j];
+        for i in (1..n).rev() {
+            let current = rows[i][j].unwrap() - SCORE_MATCH - bonus[j];
+            j = (0..j)
+                .rev()
+                .find(|&k| {
+                    let transition = match j - k {
+                        1 => BONUS_CONSECUTIVE,
+                        gap => -PENALTY_GAP_START - PENALTY_GAP_EXTENSION * (gap as i64 - 2),
+                    };
+                    rows[i - 1][k].map(|s| s + transition) == Some(current)
+                })
+                .unwrap();
+            positions.push(j);
+        }
+        positions.reverse();
+
+        Some((score, positions))
+    }
+}
+
+/// Extra points for matches at the start of a word.
+fn boundary_bonus(haystack: &[char], j: usize) -> i64 {
+    if j == 0 {
+        return BONUS_BOUNDARY;
+    }
+
+    let (prev, c) = (haystack[j - 1], haystack[j]);
+    let after_separator = !prev.is_alphanumeric();
+    let camel_case = prev.is_lowercase() && c.is_uppercase();
+    if after_separator || camel_case {
+        return BONUS_BOUNDARY;
+    }
+
+    0
+}
+
+/// Turns sorted char positions into byte ranges, merging neighbouring chars.
+fn char_spans(item: &str, positions: &[usize]) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    let mut positions = positions.iter().peekable();
+
+    for (n, (offset, c)) in item.char_indices().enumerate() {
+        if positions.peek() != Some(&&n) {
+            continue;
+        }
+        positions.next();
+
+        let end = offset + c.len_utf8();
+        match spans.last_mut() {
+            Some(last) if last.end == offset => last.end = end,
+            _ => spans.push(offset..end),
+        }
+    }
+
+    spans
+}
+
+impl SearchEngine for FuzzyEngine {
+    fn search(&mut self, needle: &String, haystack: &Vec<String>) -> Vec<Match> {
+        if needle.is_empty() {
+            return haystack.iter().map(|i| Match::new(i, Vec::new())).collect();
+        }
+
+        let case_sensitive = is_case_sensitive(self.case, needle);
+        let needle: Vec<char> = prepare(needle, case_sensitive).chars().collect();
+        let mut scored: Vec<(i64, Match)> = haystack
+            .iter()
+            .filter_map(|item| {
+                let chars: Vec<char> = item.chars().collect();
+                let (score, positions) = self.score(&needle, &chars, case_sensitive)?;
+                Some((score, Match::new(item, char_spans(item, &positions))))
+            })
+            .collect();
+
+        // sort_by is stable, so equal scores keep their input order
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        scored.into_iter().map(|(_, found)| found).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CaseMode::*;
+    use crate::Menu;
+
+    fn menu_with(engine: Box<dyn SearchEngine>, input: &[&str]) -> Menu {
+        Menu::new(engine, input.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn prefix_engine_ranks_exact_then_prefix_then_substring() {
+        let mut menu = menu_with(
+            Box::from(PrefixEngine::new(Sensitive)),
+            &["gvim", "nvi", "vim", "view", "vi"],
+        );
+        menu.search("vi".to_string());
+        assert_eq!(menu.get_items(), &vec!["vi", "vim", "view", "gvim", "nvi"]);
+    }
+
+    #[test]
+    fn prefix_engine_keeps_input_order_within_a_tier() {
+        let mut menu = menu_with(
+            Box::from(PrefixEngine::new(Sensitive)),
+            &["xterm-b", "st", "xterm-a", "uxterm", "axterm", "xterm"],
+        );
+        menu.search("xterm".to_string());
+        assert_eq!(
+            menu.get_items(),
+            &vec!["xterm", "xterm-b", "xterm-a", "uxterm", "axterm"]
+        );
+    }
+
+    #[test]
+    fn prefix_engine_respects_case_sensitivity() {
+        let input = ["Firefox", "firefox-esr", "librewolf"];
+
+        let mut menu = menu_with(Box::from(PrefixEngine::new(Sensitive)), &input);
+        menu.search("fire".to_string());
+        assert_eq!(menu.get_items(), &vec!["firefox-esr"]);
+
+        let mut menu = menu_with(Box::from(PrefixEngine::new(Insensitive)), &input);
+        menu.search("fire".to_string());
+        assert_eq!(menu.get_items(), &vec!["Firefox", "firefox-esr"]);
+    }
+
+    #[test]
+    fn prefix_engine_returns_everything_for_an_empty_search() {
+        let mut menu = menu_with(Box::from(PrefixEngine::new(Sensitive)), &["b", "a", "c"]);
+        menu.search("".to_string());
+        assert_eq!(menu.get_items(), &vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn smart_case_only_cares_about_case_with_uppercase_needles() {
+        let input = ["Firefox", "firefox-esr"];
+
+        let mut menu = menu_with(Box::from(ContainsEngine::new(Smart)), &input);
+        menu.search("fire".to_string());
+        assert_eq!(menu.get_items(), &vec!["Firefox", "firefox-esr"]);
+
+        menu.search("Fire".to_string());
+        assert_eq!(menu.get_items(), &vec!["Firefox"]);
+    }
+
+    #[test]
+    fn case_insensitive_matching_folds_unicode_one_char_at_a_time() {
+        let input = ["ΟΔΥΣΣΕΥΣ", "Straße", "ÆON"];
+
+        let mut menu = menu_with(Box::from(FuzzyEngine::new(Insensitive)), &input);
+        menu.search("οδυσσευς".to_string());
+        assert_eq!(menu.get_items(), &vec!["ΟΔΥΣΣΕΥΣ"]);
+
+        let mut menu = menu_with(Box::from(ContainsEngine::new(Insensitive)), &input);
+        // only simple folding, ß does not turn into ss
+        menu.search("STRASSE".to_string());
+        assert!(menu.get_items().is_empty());
+        menu.search("STRAẞE".to_string());
+        assert_eq!(menu.get_items(), &vec!["Straße"]);
+        menu.search("æon".to_string());
+        assert_eq!(menu.get_items(), &vec!["ÆON"]);
+    }
+
+    #[test]
+    fn fuzzy_engine_prefers_dense_matches_over_scattered_word_starts() {
+        let mut menu = menu_with(
+            Box::from(FuzzyEngine::new(Sensitive)),
+            &["xfce4-terminal-fx", "fontforge-x", "ffmpeg-x", "firefox"],
+        );
+        menu.search("ffx".to_string());
+        assert_eq!(
+            menu.get_items(),
+            &vec!["firefox", "ffmpeg-x", "fontforge-x", "xfce4-terminal-fx"]
+        );
+    }
+
+    #[test]
+    fn fuzzy_engine_only_rewards_short_gaps_before_word_starts() {
+        let mut menu = menu_with(
+            Box::from(FuzzyEngine::new(Sensitive)),
+            &["axxb", "a--b", "axb", "a-b", "ab"],
+        );
+        menu.search("ab".to_string());
+        assert_eq!(menu.get_items(), &vec!["ab", "a-b", "axb", "a--b", "axxb"]);
+    }
+
+    #[test]
+    fn fuzzy_engine_prefers_consecutive_matches() {
+        let mut menu = menu_with(Box::from(FuzzyEngine::new(Sensitive)), &["axbxc", "xabcx"]);
+        menu.search("abc".to_string());
+        assert_eq!(menu.get_items(), &vec!["xabcx", "axbxc"]);
+    }
+
+    #[test]
+    fn fuzzy_engine_prefers_word_starts() {
+        let mut menu = menu_with(
+            Box::from(FuzzyEngine::new(Sensitive)),
+            &["foobar", "foo-bar", "fooBar"],
+        );
+        menu.search("bar".to_string());
+        assert_eq!(menu.get_items(), &vec!["foo-bar", "foobar"]);
+
+        menu.search("Bar".to_string());
+        assert_eq!(menu.get_items(), &vec!["fooBar"]);
+
+        let mut menu = menu_with(
+            Box::from(FuzzyEngine::new(Insensitive)),
+            &["foobar", "fooBar"],
+        );
+        menu.search("bar".to_string());
+        assert_eq!(menu.get_items(), &vec!["fooBar", "foobar"]);
+    }
+
+    #[test]
+    fn token_engine_requires_every_token_in_any_order() {
+        let mut menu = menu_with(
+            Box::from(TokenEngine::new(Sensitive)),
+            &["git-log-viewer", "log-git", "git-status", "changelog"],
+        );
+        menu.search("git log".to_string());
+        assert_eq!(menu.get_items(), &vec!["git-log-viewer", "log-git"]);
+
+        menu.search("  log   git ".to_string());
+        assert_eq!(menu.get_items(), &vec!["git-log-viewer", "log-git"]);
+    }
+
+    #[test]
+    fn token_engine_supports_negation() {
+        let mut menu = menu_with(
+            Box::from(TokenEngine::new(Sensitive)),
+            &["git-log-viewer", "log-git", "git-status"],
+        );
+        menu.search("git !viewer".to_string());
+        assert_eq!(menu.get_items(), &vec!["log-git", "git-status"]);
+
+        // a lone ! is ignored while it is being typed
+        menu.search("git !".to_string());
+        assert_eq!(menu.get_items().len(), 3);
+    }
+
+    #[test]
+    fn token_engine_supports_anchors() {
+        let mut menu = menu_with(
+            Box::from(TokenEngine::new(Sensitive)),
+            &["git-log-viewer", "log-git", "git", "gitk"],
+        );
+        menu.search("^git".to_string());
+        assert_eq!(menu.get_items(), &vec!["git-log-viewer", "git", "gitk"]);
+
+        menu.search("git$".to_string());
+        assert_eq!(menu.get_items(), &vec!["log-git", "git"]);
+
+        menu.search("^git$".to_string());
+        assert_eq!(menu.get_items(), &vec!["git"]);
+
+        menu.search("git !^git".to_string());
+        assert_eq!(menu.get_items(), &vec!["log-git"]);
+    }
+
+    #[test]
+    fn token_engine_applies_the_case_mode_to_every_token() {
+        let mut menu = menu_with(
+            Box::from(TokenEngine::new(Insensitive)),
+            &["Git-Log", "git-status"],
+        );
+        menu.search("^git LOG".to_string());
+        assert_eq!(menu.get_items(), &vec!["Git-Log"]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_engine_keeps_the_last_good_result_for_invalid_patterns() {
+        let mut menu = menu_with(
+            Box::from(regex_engine::RegexEngine::new(Sensitive)),
+            &["var/log/syslog", "var/log/auth.log", "etc/hosts"],
+        );
+        menu.search("log/(sys".to_string());
+        assert_eq!(menu.get_items().len(), 3);
+        assert!(menu.get_search_error().is_some());
+
+        menu.search("log/(sys|auth)".to_string());
+        assert_eq!(
+            menu.get_items(),
+            &vec!["var/log/syslog", "var/log/auth.log"]
+        );
+        assert!(menu.get_search_error().is_none());
+
+        menu.search("log/(sys|auth".to_string());
+        assert_eq!(
+            menu.get_items(),
+            &vec!["var/log/syslog", "var/log/auth.log"]
+        );
+        assert!(menu.get_search_error().is_some());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_engine_smart_case_ignores_uppercase_escapes() {
+        let mut menu = menu_with(
+            Box::from(regex_engine::RegexEngine::new(Smart)),
+            &["Foo bar", "foo"],
+        );
+        menu.search("fo\\S".to_string());
+        assert_eq!(menu.get_items(), &vec!["Foo bar", "foo"]);
+
+        menu.search("\\Bo\\p{Ll}".to_string());
+        assert_eq!(menu.get_items(), &vec!["Foo bar", "foo"]);
+
+        menu.search("(?P<Word>foo)\\W".to_string());
+        assert_eq!(menu.get_items(), &vec!["Foo bar"]);
+
+        menu.search("Fo\\S".to_string());
+        assert_eq!(menu.get_items(), &vec!["Foo bar"]);
+    }
+
+    #[test]
+    fn engines_report_match_spans_in_bytes_of_the_original_item() {
+        let haystack = vec!["firefox".to_string(), "ÆON flux".to_string()];
+
+        let fuzzy = FuzzyEngine::new(Sensitive).search(&"ffx".to_string(), &haystack);
+        assert_eq!(fuzzy, vec![Match::new("firefox", vec![0..1, 4..5, 6..7])]);
+
+        let contains = ContainsEngine::new(Insensitive).search(&"on".to_string(), &haystack);
+        assert_eq!(contains.len(), 1);
+        assert_eq!(contains[0].spans.first(), Some(&(2..4)));
+
+        let tokens = TokenEngine::new(Sensitive).search(&"flux ^Æ".to_string(), &haystack);
+        assert_eq!(tokens, vec![Match::new("ÆON flux", vec![0..2, 5..9])]);
+    }
+}
@@ -15,25 +15,342 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+#include <locale.h>
+#include <stdlib.h>
+#include <string.h>
+#include <wchar.h>
 #include <X11/Xlib.h>
+#include <X11/Xlibint.h>
+#include <X11/Xlib-xcb.h>
+#include <X11/Xutil.h>
 
-int keycode_to_utf8(unsigned int keycode, unsigned int mask, char *buffer) {
-  Display* display = XOpenDisplay(":0");
+#define PREEDIT_MAX 256
+#define GENERIC_EVENT 35
 
-  XIM xim = XOpenIM(display, 0, 0, 0);
-  XIC xic = XCreateIC(xim, XNInputStyle, XIMPreeditNothing | XIMStatusNothing, NULL);
+/* one Xlib display for the lifetime of moemenu, its xcb connection is shared with x11rb */
+struct moemenu_display {
+  Display *display;
+  XIM xim;
+  XIC xic;
+  Window window;
 
-  XKeyPressedEvent event;
-  event.type = KeyPress;
-  event.display = display;
-  event.state = mask;
-  event.keycode = keycode;
+  /* text of the last key, grows when the input method commits a lot at once */
+  char *text;
+  int text_size;
 
+  /* on-the-spot preedit text the input method is composing */
+  XICCallback preedit_start;
+  XICCallback preedit_done;
+  XICCallback preedit_draw;
+  XICCallback preedit_caret;
+  wchar_t preedit[PREEDIT_MAX];
+  int preedit_length;
+  int preedit_cursor;
+  int preedit_changed;
+};
+
+struct moemenu_display *display_open(void) {
+  /* the input method and the text it returns depend on the locale */
+  setlocale(LC_CTYPE, "");
+  XSetLocaleModifiers("");
+
+  /* NULL means $DISPLAY */
+  Display *display = XOpenDisplay(NULL);
+  if (display == NULL) {
+    return NULL;
+  }
+
+  /* x11rb reads the events, Xlib only gets to see them through display_filter_event */
+  XSetEventQueueOwner(display, XCBOwnsEventQueue);
+
+  struct moemenu_display *d = calloc(1, sizeof(struct moemenu_display));
+  if (d == NULL) {
+    XCloseDisplay(display);
+    return NULL;
+  }
+  d->display = display;
+  d->text_size = 64;
+  d->text = malloc(d->text_size);
+  if (d->text == NULL) {
+    free(d);
+    XCloseDisplay(display);
+    return NULL;
+  }
+  d->xim = XOpenIM(display, NULL, NULL, NULL);
+  if (d->xim == NULL) {
+    /* XMODIFIERS names an input method that is not running, use the builtin one */
+    XSetLocaleModifiers("@im=none");
+    d->xim = XOpenIM(display, NULL, NULL, NULL);
+  }
+
+  return d;
+}
+
+void display_close(struct moemenu_display *d) {
+  if (d->xic != NULL) {
+    XDestroyIC(d->xic);
+  }
+  if (d->xim != NULL) {
+    XCloseIM(d->xim);
+  }
+  XCloseDisplay(d->display);
+  free(d->text);
+  free(d);
+}
+
+xcb_connection_t *display_xcb_connection(struct moemenu_display *d) {
+  return XGetXCBConnection(d->display);
+}
+
+int display_default_screen(struct moemenu_display *d) {
+  return DefaultScreen(d->display);
+}
+
+/* Xlib caches the keyboard mapping but never sees a MappingNotify itself */
+void display_refresh_keyboard_mapping(struct moemenu_display *d, int first_keycode, int count) {
+  XMappingEvent event = {0};
+  event.type = MappingNotify;
+  event.display = d->display;
+  event.request = MappingKeyboard;
+  event.first_keycode = first_keycode;
+  event.count = count;
+  XRefreshKeyboardMapping(&event);
+}
+
+static int preedit_start(XIC xic, XPointer client, XPointer call) {
+  struct moemenu_display *d = (struct moemenu_display *) client;
+  d->preedit_length = 0;
+  d->preedit_cursor = 0;
+  d->preedit_changed = 1;
+  return PREEDIT_MAX;
+}
+
+static void preedit_done(XIC xic, XPointer client, XPointer call) {
+  struct moemenu_display *d = (struct moemenu_display *) client;
+  d->preedit_length = 0;
+  d->preedit_cursor = 0;
+  d->preedit_changed = 1;
+}
+
+static void preedit_draw(XIC xic, XPointer client, XIMPreeditDrawCallbackStruct *call) {
+  struct moemenu_display *d = (struct moemenu_display *) client;
+
+  int first = call->chg_first;
+  int removed = call->chg_length;
+  if (first < 0 || removed < 0 || first + removed > d->preedit_length) {
+    return;
+  }
+
+  wchar_t inserted[PREEDIT_MAX];
+  int count = 0;
+  if (call->text != NULL && call->text->length > 0) {
+    if (call->text->encoding_is_wchar) {
+      count = call->text->length;
+      if (count > PREEDIT_MAX) {
+        count = PREEDIT_MAX;
+      }
+      memcpy(inserted, call->text->string.wide_char, count * sizeof(wchar_t));
+    } else if (call->text->string.multi_byte != NULL) {
+      size_t converted = mbstowcs(inserted, call->text->string.multi_byte, PREEDIT_MAX);
+      count = converted == (size_t) -1 ? 0 : (int) converted;
+    }
+  }
+
+  int tail = d->preedit_length - first - removed;
+  if (first + count + tail > PREEDIT_MAX) {
+    count = PREEDIT_MAX - first - tail;
+  }
+
+  memmove(d->preedit + first + count, d->preedit + first + removed, tail * sizeof(wchar_t));
+  memcpy(d->preedit + first, inserted, count * sizeof(wchar_t));
+  d->preedit_length = first + count + tail;
+  d->preedit_cursor = call->caret;
+  d->preedit_changed = 1;
+}
+
+static void preedit_caret(XIC xic, XPointer client, XIMPreeditCaretCallbackStruct *call) {
+  struct moemenu_display *d = (struct moemenu_display *) client;
+
+  switch (call->direction) {
+  case XIMAbsolutePosition:
+    d->preedit_cursor = call->position;
+    break;
+  case XIMForwardChar:
+    d->preedit_cursor++;
+    break;
+  case XIMBackwardChar:
+    d->preedit_cursor--;
+    break;
+  case XIMLineStart:
+    d->preedit_cursor = 0;
+    break;
+  case XIMLineEnd:
+    d->preedit_cursor = d->preedit_length;
+    break;
+  default:
+    break;
+  }
+
+  if (d->preedit_cursor < 0) {
+    d->preedit_cursor = 0;
+  } else if (d->preedit_cursor > d->preedit_length) {
+    d->preedit_cursor = d->preedit_length;
+  }
+  call->position = d->preedit_cursor;
+  d->preedit_changed = 1;
+}
+
+/* picks on-the-spot preedit if the input method supports it */
+static XIMStyle choose_style(XIM xim) {
+  XIMStyle fallback = XIMPreeditNothing | XIMStatusNothing;
+  XIMStyles *styles = NULL;
+  if (XGetIMValues(xim, XNQueryInputStyle, &styles, NULL) != NULL || styles == NULL) {
+    return fallback;
+  }
+
+  XIMStyle style = fallback;
+  for (int i = 0; i < styles->count_styles; i++) {
+    if (styles->supported_styles[i] == (XIMPreeditCallbacks | XIMStatusNothing)) {
+      style = styles->supported_styles[i];
+      break;
+    }
+  }
+  XFree(styles);
+  return style;
+}
+
+/* binds the input context to the window, returns the events the input method wants to see */
+unsigned long display_create_input_context(struct moemenu_display *d, Window window) {
+  d->window = window;
+  if (d->xim == NULL) {
+    return 0;
+  }
+
+  XIMStyle style = choose_style(d->xim);
+  if (style & XIMPreeditCallbacks) {
+    d->preedit_start.client_data = (XPointer) d;
+    d->preedit_start.callback = (XICProc) preedit_start;
+    d->preedit_done.client_data = (XPointer) d;
+    d->preedit_done.callback = (XICProc) preedit_done;
+    d->preedit_draw.client_data = (XPointer) d;
+    d->preedit_draw.callback = (XICProc) preedit_draw;
+    d->preedit_caret.client_data = (XPointer) d;
+    d->preedit_caret.callback = (XICProc) preedit_caret;
+
+    XVaNestedList preedit = XVaCreateNestedList(0,
+        XNPreeditStartCallback, &d->preedit_start,
+        XNPreeditDoneCallback, &d->preedit_done,
+        XNPreeditDrawCallback, &d->preedit_draw,
+        XNPreeditCaretCallback, &d->preedit_caret,
+        NULL);
+    d->xic = XCreateIC(d->xim, XNInputStyle, style, XNClientWindow, window,
+        XNFocusWindow, window, XNPreeditAttributes, preedit, NULL);
+    XFree(preedit);
+  }
+  if (d->xic == NULL) {
+    d->xic = XCreateIC(d->xim, XNInputStyle, XIMPreeditNothing | XIMStatusNothing,
+        XNClientWindow, window, XNFocusWindow, window, NULL);
+  }
+  if (d->xic == NULL) {
+    return 0;
+  }
+
+  XSetICFocus(d->xic);
+  unsigned long events = 0;
+  XGetICValues(d->xic, XNFilterEvents, &events, NULL);
+  return events;
+}
+
+/*
+ * Hands an event read by xcb to the input method. Returns 1 if it was consumed.
+ * Key presses that get through are queued in Xlib and come out of display_next_key,
+ * in order with the ones the input method commits or forwards.
+ */
+int display_filter_event(struct moemenu_display *d, const unsigned char *wire) {
+  int type = wire[0] & 0x7f;
+  if (type == GENERIC_EVENT) {
+    return 0;
+  }
+
+  /* there is no public way to turn a wire event into an XEvent, borrow the converter */
+  Bool (*convert)(Display *, XEvent *, xEvent *) = XESetWireToEvent(d->display, type, NULL);
+  XESetWireToEvent(d->display, type, convert);
+
+  xEvent copy;
+  memcpy(&copy, wire, sizeof(xEvent));
+  copy.u.u.sequenceNumber = LastKnownRequestProcessed(d->display);
+  XEvent event;
+  if (!convert(d->display, &event, &copy)) {
+    return 0;
+  }
+
+  int is_key = type == KeyPress || type == KeyRelease;
+  if (XFilterEvent(&event, is_key ? d->window : None)) {
+    return 1;
+  }
+
+  if (type == KeyPress) {
+    XPutBackEvent(d->display, &event);
+  }
+  return 0;
+}
+
+static int lookup_text(struct moemenu_display *d, XKeyEvent *event) {
   KeySym ignore;
-  Status return_status;
-  Xutf8LookupString(xic, &event, buffer, 32, &ignore, &return_status);
-  XDestroyIC(xic);
-  XCloseIM(xim);
-  XCloseDisplay(display);
-  return return_status;
+  Status status;
+
+  if (d->xic == NULL) {
+    /* without an input method only ASCII is safe, XLookupString returns Latin-1 */
+    int length = XLookupString(event, d->text, d->text_size - 1, &ignore, NULL);
+    if (length > 0 && (unsigned char) d->text[0] >= 0x80) {
+      length = 0;
+    }
+    return length;
+  }
+
+  int length = Xutf8LookupString(d->xic, event, d->text, d->text_size - 1, &ignore, &status);
+  if (status == XBufferOverflow) {
+    d->text_size = length + 1;
+    d->text = realloc(d->text, d->text_size);
+    length = Xutf8LookupString(d->xic, event, d->text, d->text_size - 1, &ignore, &status);
+  }
+
+  if (status != XLookupChars && status != XLookupBoth) {
+    return 0;
+  }
+  return length;
+}
+
+/*
+ * Takes the next key press out of Xlib. Committed text of the input method has keycode 0.
+ * The returned text is valid until the next call.
+ */
+const char *display_next_key(struct moemenu_display *d, unsigned int *keycode, unsigned int *state, int *length) {
+  while (XEventsQueued(d->display, QueuedAlready) > 0) {
+    XEvent event;
+    XNextEvent(d->display, &event);
+    if (event.type != KeyPress) {
+      continue;
+    }
+
+    *keycode = event.xkey.keycode;
+    *state = event.xkey.state;
+    *length = lookup_text(d, &event.xkey);
+    return d->text;
+  }
+
+  return NULL;
+}
+
+/* returns 1 and the preedit text if it changed since the last call */
+int display_preedit(struct moemenu_display *d, const wchar_t **text, int *length, int *cursor) {
+  if (!d->preedit_changed) {
+    return 0;
+  }
+
+  d->preedit_changed = 0;
+  *text = d->preedit;
+  *length = d->preedit_length;
+  *cursor = d->preedit_cursor;
+  return 1;
 }
@@ -19,16 +19,19 @@ use std::error::Error;
 use std::{fmt, thread, time};
 
 use x11rb::atom_manager;
-use x11rb::connection::Connection;
+use x11rb::connection::{Connection, RequestConnection as _};
 use x11rb::errors::{ReplyError, ReplyOrIdError};
 use x11rb::protocol::render::{self, ConnectionExt as _, PictType};
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::{ConnectionExt as _, *};
 use x11rb::protocol::Event;
 use x11rb::wrapper::ConnectionExt;
 use x11rb::xcb_ffi::XCBConnection;
 
-use crate::config::Position;
-use crate::draw::{do_draw, set_color};
+use crate::config::{Dimension, Monitor, Position};
+use crate::draw::{do_draw, set_color, Hit, Layout, Page};
+use crate::keys::{modifier, Action, Bindings, GroupWrap, KeySyms, Keymap};
+use crate::resources;
 use crate::{draw, Config, Menu, UserInterface};
 
 atom_manager! {
@@ -37,6 +40,10 @@ atom_manager! {
         WM_DELETE_WINDOW,
         _NET_WM_NAME,
         UTF8_STRING,
+        CLIPBOARD,
+        INCR,
+        // where the owner of a selection puts its content for moemenu
+        MOEMENU_PASTE,
     }
 }
 
@@ -48,7 +55,46 @@ pub struct XorgUserInterface {
     window: Window,
     atoms: AtomCollection,
     surface: cairo::XCBSurface,
+    bindings: Bindings,
+    keymap: Keymap,
+    xkb: bool,
+    paste: Option<Paste>,
     config: Config,
+    // dropped last since it owns the connection
+    display: sys::Display,
+}
+
+/// The Xlib display and the x11rb connection sharing it, open before the config is loaded so the
+/// X resources can be read
+pub struct XorgConnection {
+    connection: XCBConnection,
+    /// read once, the config and the scale both need them
+    resources: String,
+    // dropped last since it owns the connection
+    display: sys::Display,
+}
+
+impl XorgConnection {
+    pub fn open() -> Result<XorgConnection, Box<dyn Error>> {
+        let display =
+            sys::Display::open().ok_or_else(|| DisplayError::new("failed to open the display"))?;
+        // SAFETY: the display owns the connection and outlives it, it is the last field
+        let connection =
+            unsafe { XCBConnection::from_raw_xcb_connection(display.xcb_connection(), false)? };
+        let root = connection.setup().roots[display.default_screen()].root;
+        let resources = get_resources(&connection, root);
+
+        Ok(XorgConnection {
+            connection,
+            resources,
+            display,
+        })
+    }
+
+    /// The resources loaded with xrdb, empty if there are none
+    pub fn resources(&self) -> &str {
+        &self.resources
+    }
 }
 
 type ShouldContinue = bool;
@@ -57,19 +103,22 @@ enum XorgUiAction {
     Redraw,
     Stop,
     Select(ShouldContinue),
+    Paste(Selection),
     None,
 }
 
-#[allow(non_snake_case)]
-mod XorgKeys {
-    use x11rb::protocol::xproto::Keycode;
+#[derive(Copy, Clone)]
+enum Selection {
+    Clipboard,
+    Primary,
+}
 
-    pub const ESC: Keycode = 9;
-    pub const ENTER: Keycode = 36;
-    pub const TAB: Keycode = 23;
-    pub const BACKSPACE: Keycode = 22;
-    pub const LEFT: Keycode = 113;
-    pub const RIGHT: Keycode = 114;
+/// A selection that is being transferred into the search term
+struct Paste {
+    target: Atom,
+    /// the owner sends large selections in chunks
+    incremental: bool,
+    data: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -125,6 +174,31 @@ impl Error for KeyboardGrabError {
     }
 }
 
+#[derive(Debug)]
+struct DisplayError {
+    details: String,
+}
+
+impl DisplayError {
+    fn new(msg: &str) -> Self {
+        DisplayError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for DisplayError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
 #[derive(Debug)]
 struct NoSelectionError {}
 
@@ -147,36 +221,125 @@ impl Error for NoSelectionError {
 }
 
 // wrapper around xorg.c
-#[allow(non_upper_case_globals)]
 mod sys {
-    use std::os::raw::c_char;
-    use x11rb::protocol::xproto::Keycode;
+    use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_void};
+    use std::slice;
+    use x11rb::protocol::xproto::{Keycode, Window};
 
-    //const XBufferOverflow: i8 = -1;
-    //const XLookupNone: i8 = 1;
-    const XLookupChars: i32 = 2;
-    //const XLookupKeySym: i8 = 3;
-    const XLookupBoth: i32 = 4;
+    #[repr(C)]
+    struct moemenu_display {
+        _private: [u8; 0],
+    }
 
     extern "C" {
-        fn keycode_to_utf8(keycode: u32, mask: u32, buffer: *mut c_char) -> i32;
+        fn display_open() -> *mut moemenu_display;
+        fn display_close(display: *mut moemenu_display);
+        fn display_xcb_connection(display: *mut moemenu_display) -> *mut c_void;
+        fn display_default_screen(display: *mut moemenu_display) -> c_int;
+        fn display_refresh_keyboard_mapping(
+            display: *mut moemenu_display,
+            first_keycode: c_int,
+            count: c_int,
+        );
+        fn display_create_input_context(display: *mut moemenu_display, window: c_ulong) -> c_ulong;
+        fn display_filter_event(display: *mut moemenu_display, event: *const u8) -> c_int;
+        fn display_next_key(
+            display: *mut moemenu_display,
+            keycode: *mut c_uint,
+            state: *mut c_uint,
+            length: *mut c_int,
+        ) -> *const c_char;
+        fn display_preedit(
+            display: *mut moemenu_display,
+            text: *mut *const u32,
+            length: *mut c_int,
+            cursor: *mut c_int,
+        ) -> c_int;
+    }
+
+    /// A key press after the input method had a look at it
+    pub struct Key {
+        /// 0 for text the input method committed
+        pub keycode: Keycode,
+        pub state: u16,
+        pub text: String,
     }
 
-    pub fn keycode_to_char(keycode: Keycode, state: u16) -> Option<char> {
-        let mut buffer: [c_char; 32] = [0; 32];
-        let status: i32 =
-            unsafe { keycode_to_utf8(keycode as u32, state as u32, buffer.as_mut_ptr()) };
+    /// The Xlib display, it owns the xcb connection and the input context
+    pub struct Display {
+        raw: *mut moemenu_display,
+    }
+
+    impl Display {
+        /// Connects to $DISPLAY
+        pub fn open() -> Option<Self> {
+            let raw = unsafe { display_open() };
+            match raw.is_null() {
+                true => None,
+                false => Some(Display { raw }),
+            }
+        }
+
+        pub fn xcb_connection(&self) -> *mut c_void {
+            unsafe { display_xcb_connection(self.raw) }
+        }
+
+        pub fn default_screen(&self) -> usize {
+            unsafe { display_default_screen(self.raw) as usize }
+        }
+
+        pub fn refresh_keyboard_mapping(&self, first_keycode: Keycode, count: u8) {
+            unsafe {
+                display_refresh_keyboard_mapping(self.raw, first_keycode as c_int, count as c_int)
+            }
+        }
+
+        /// Returns the event mask the input method needs on the window
+        pub fn create_input_context(&self, window: Window) -> u32 {
+            unsafe { display_create_input_context(self.raw, window as c_ulong) as u32 }
+        }
+
+        /// Returns true if the input method consumed the event
+        pub fn filter_event(&self, event: &[u8]) -> bool {
+            // every event starts with 32 bytes, only generic events can be longer
+            if event.len() < 32 {
+                return false;
+            }
+            unsafe { display_filter_event(self.raw, event.as_ptr()) != 0 }
+        }
+
+        pub fn next_key(&self) -> Option<Key> {
+            let (mut keycode, mut state, mut length) = (0, 0, 0);
+            let text = unsafe { display_next_key(self.raw, &mut keycode, &mut state, &mut length) };
+            if text.is_null() {
+                return None;
+            }
 
-        // if we received bytes try converting them into a char
-        if status == XLookupChars || status == XLookupBoth {
-            // why does rust define c_char as i8 ???
-            let proper_bytes: Vec<u8> = buffer.to_vec().iter().map(|x| x.clone() as u8).collect();
-            let str = std::str::from_utf8(proper_bytes.as_slice()).unwrap();
-            let trimmed = str.trim_matches(char::from(0));
-            return trimmed.chars().next();
+            let bytes = unsafe { slice::from_raw_parts(text as *const u8, length.max(0) as usize) };
+            Some(Key {
+                keycode: keycode as Keycode,
+                state: state as u16,
+                text: String::from_utf8_lossy(bytes).into_owned(),
+            })
         }
 
-        None
+        /// The text being composed and the char index of its cursor, if it changed
+        pub fn preedit(&self) -> Option<(String, usize)> {
+            let (mut text, mut length, mut cursor) = (std::ptr::null(), 0, 0);
+            if unsafe { display_preedit(self.raw, &mut text, &mut length, &mut cursor) } == 0 {
+                return None;
+            }
+
+            let chars = unsafe { slice::from_raw_parts(text, length.max(0) as usize) };
+            let text = chars.iter().filter_map(|c| char::from_u32(*c)).collect();
+            Some((text, cursor.max(0) as usize))
+        }
+    }
+
+    impl Drop for Display {
+        fn drop(&mut self) {
+            unsafe { display_close(self.raw) }
+        }
     }
 }
 
@@ -245,111 +408,289 @@ fn composite_manager_running(
     Ok(owner.owner != x11rb::NONE)
 }
 
-/// returns the position and size of the focused monitor
+/// A monitor in root window coordinates
+#[derive(Clone)]
+struct MonitorGeometry {
+    /// only known with RandR
+    #[cfg(feature = "multimonitor")]
+    name: Option<String>,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    /// physical size, 0 if unknown
+    width_mm: u32,
+}
+
 #[cfg(feature = "multimonitor")]
-fn handle_multi_monitor<C>(conn: &C, root: Window) -> Option<(i16, i16, u16, u16)>
-where
-    C: Connection,
-{
-    use x11rb::cookie::Cookie;
-    use x11rb::errors::ConnectionError;
-    use x11rb::protocol::xinerama::{ConnectionExt as _, *};
-    use x11rb::x11_utils::TryParse;
-
-    // hecking generics (maybe define as macro?)
-    fn unwrap_cookie<C, R>(cookie: Result<Cookie<C, R>, ConnectionError>) -> Option<R>
-    where
-        C: Connection,
-        R: TryParse,
+impl MonitorGeometry {
+    fn contains(&self, (x, y): (i16, i16)) -> bool {
+        let (x, y) = (x as i32, y as i32);
+        let (left, top) = (self.x as i32, self.y as i32);
+        left <= x && x < left + self.width as i32 && top <= y && y < top + self.height as i32
+    }
+}
+
+/// Lists the monitors with RandR 1.5 and falls back to Xinerama
+#[cfg(feature = "multimonitor")]
+fn get_monitors(conn: &impl Connection, root: Window) -> Vec<MonitorGeometry> {
+    use x11rb::protocol::randr::{self, ConnectionExt as _};
+    use x11rb::protocol::xinerama::ConnectionExt as _;
+
+    let has_monitors = conn
+        .extension_information(randr::X11_EXTENSION_NAME)
+        .is_ok_and(|info| info.is_some())
+        && conn
+            .randr_query_version(1, 5)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some_and(|v| (v.major_version, v.minor_version) >= (1, 5));
+
+    if has_monitors {
+        if let Some(reply) = conn
+            .randr_get_monitors(root, true)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+        {
+            return reply
+                .monitors
+                .iter()
+                .map(|monitor| MonitorGeometry {
+                    name: conn
+                        .get_atom_name(monitor.name)
+                        .ok()
+                        .and_then(|cookie| cookie.reply().ok())
+                        .map(|reply| String::from_utf8_lossy(&reply.name).into_owned()),
+                    x: monitor.x,
+                    y: monitor.y,
+                    width: monitor.width,
+                    height: monitor.height,
+                    width_mm: monitor.width_in_millimeters,
+                })
+                .collect();
+        }
+    }
+
+    match conn
+        .xinerama_query_screens()
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
     {
-        if let Ok(cookie) = cookie {
-            if let Ok(reply) = cookie.reply() {
-                return Some(reply);
-            }
-        };
-        None
-    };
+        Some(reply) => reply
+            .screen_info
+            .iter()
+            .map(|info| MonitorGeometry {
+                name: None,
+                x: info.x_org,
+                y: info.y_org,
+                width: info.width,
+                height: info.height,
+                width_mm: 0,
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
 
-    let get_screen_info =
-        |conn: &C| -> Option<QueryScreensReply> { unwrap_cookie(conn.xinerama_query_screens()) };
+/// Center of the top-level window that has the focus
+#[cfg(feature = "multimonitor")]
+fn get_focus_point(conn: &impl Connection, root: Window) -> Option<(i16, i16)> {
+    let focus = conn.get_input_focus().ok()?.reply().ok()?.focus;
+    // no focus or PointerRoot
+    if focus == x11rb::NONE || focus == 1 || focus == root {
+        return None;
+    }
 
-    let get_focused_window = |conn: &C| -> Option<Window> {
-        if let Some(reply) = unwrap_cookie(conn.get_input_focus()) {
-            // this will always return a focused window
-            // should find a way to check if the focus is an actual window or not
-            return Some(reply.focus);
+    // reparenting window managers put the client into a frame, that one has the real position
+    let mut window = focus;
+    loop {
+        let tree = conn.query_tree(window).ok()?.reply().ok()?;
+        if tree.parent == root || tree.parent == x11rb::NONE {
+            break;
         }
-        None
-    };
+        window = tree.parent;
+    }
 
-    let get_geometry =
-        |conn: &C, w| -> Option<GetGeometryReply> { unwrap_cookie(conn.get_geometry(w)) };
+    let geometry = conn.get_geometry(window).ok()?.reply().ok()?;
+    let translated = conn
+        .translate_coordinates(window, root, 0, 0)
+        .ok()?
+        .reply()
+        .ok()?;
+    Some((
+        translated.dst_x.saturating_add((geometry.width / 2) as i16),
+        translated
+            .dst_y
+            .saturating_add((geometry.height / 2) as i16),
+    ))
+}
 
-    let get_window_tree = |conn: &C, w| unwrap_cookie(conn.query_tree(w));
+#[cfg(feature = "multimonitor")]
+fn get_pointer_point(conn: &impl Connection, root: Window) -> Option<(i16, i16)> {
+    let pointer = conn.query_pointer(root).ok()?.reply().ok()?;
+    Some((pointer.root_x, pointer.root_y))
+}
 
-    let translate_coordinates = |conn: &C, src, dst| -> Option<TranslateCoordinatesReply> {
-        if let Some(geo) = get_geometry(conn, src) {
-            return unwrap_cookie(conn.translate_coordinates(src, dst, geo.x, geo.y));
-        }
-        None
+/// returns the monitor picked in the config
+#[cfg(feature = "multimonitor")]
+fn handle_multi_monitor(
+    conn: &impl Connection,
+    root: Window,
+    monitor: &Monitor,
+) -> Option<MonitorGeometry> {
+    let monitors = get_monitors(conn, root);
+    let on_point = |point: Option<(i16, i16)>| {
+        let point = point?;
+        monitors.iter().find(|m| m.contains(point))
     };
 
-    let get_coords = |conn: &C, w| -> Option<(i16, i16)> {
-        if let Some(tree) = get_window_tree(&conn, w) {
-            let root = tree.root;
-            if let Some(translated) = translate_coordinates(conn, w, root) {
-                return Some((translated.dst_x, translated.dst_y));
-            }
-        }
-        None
+    let picked = match monitor {
+        Monitor::Index(index) => monitors.get(*index),
+        Monitor::Name(name) => monitors.iter().find(|m| m.name.as_ref() == Some(name)),
+        Monitor::Pointer => on_point(get_pointer_point(conn, root)),
+        Monitor::Focused => on_point(get_focus_point(conn, root))
+            .or_else(|| on_point(get_pointer_point(conn, root))),
     };
 
-    let on_screen = |x: i16, y: i16, screen: ScreenInfo| -> bool {
-        if screen.x_org <= x
-            && x <= screen.x_org + screen.width as i16
-            && screen.y_org <= y
-            && y <= screen.y_org + screen.height as i16
-        {
-            return true;
-        }
-
-        false
-    };
+    picked
+        .or_else(|| on_point(get_pointer_point(conn, root)))
+        .cloned()
+}
 
-    let handle = |conn: &C| -> Option<(i16, i16, u16, u16)> {
-        let point: Option<(i16, i16)> = (|| {
-            // try to find focused monitor based on the focused window
-            if let Some(window) = get_focused_window(conn) {
-                if let Some((x, y)) = get_coords(conn, window) {
-                    if x > -1 && y > -1 {
-                        return Some((x, y));
-                    }
-                }
+/// The monitor moemenu shows up on, the whole screen without multimonitor support
+fn pick_monitor(_conn: &impl Connection, screen: &Screen, _monitor: &Monitor) -> MonitorGeometry {
+    #[cfg(feature = "multimonitor")]
+    if let Some(geometry) = handle_multi_monitor(_conn, screen.root, _monitor) {
+        return geometry;
+    }
 
-                // try to base it on the pointer location instead
-                if let Some(pointer) = unwrap_cookie(conn.query_pointer(root)) {
-                    return Some((pointer.root_x, pointer.root_y));
-                }
-            }
+    MonitorGeometry {
+        #[cfg(feature = "multimonitor")]
+        name: None,
+        x: 0,
+        y: 0,
+        width: screen.width_in_pixels,
+        height: screen.height_in_pixels,
+        width_mm: screen.width_in_millimeters.into(),
+    }
+}
 
-            None
-        })();
+/// The RESOURCE_MANAGER property of the root window, empty if xrdb never ran
+fn get_resources(conn: &impl Connection, root: Window) -> String {
+    conn.get_property(
+        false,
+        root,
+        AtomEnum::RESOURCE_MANAGER,
+        AtomEnum::STRING,
+        0,
+        100_000_000,
+    )
+    .ok()
+    .and_then(|cookie| cookie.reply().ok())
+    .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+    .unwrap_or_default()
+}
 
-        if let Some((x, y)) = point {
-            if let Some(screens) = get_screen_info(&conn) {
-                for info in screens.screen_info {
-                    if on_screen(x, y, info) {
-                        return Some((info.x_org, info.y_org, info.width, info.height));
-                    }
-                }
-            }
+/// Scale for Xft.dpi, or for the pixel density of the monitor
+fn detect_scale(resources: &str, monitor: &MonitorGeometry) -> f64 {
+    if let Some(Ok(dpi)) = resources::get(resources, "Xft.dpi").map(str::parse::<f64>) {
+        if dpi > 0.0 {
+            return dpi / 96.0;
         }
+    }
 
-        None
+    if monitor.width_mm == 0 {
+        return 1.0;
+    }
+    let dpi = monitor.width as f64 * 25.4 / monitor.width_mm as f64;
+    // quarter steps, odd sizes reported by monitors should not make everything smaller
+    ((dpi / 96.0 * 4.0).round() / 4.0).max(1.0)
+}
+
+/// Turns on XKB if the server supports it, so the keyboard group shows up in the key events
+fn use_xkb(conn: &impl Connection) -> bool {
+    let supported = match conn.xkb_use_extension(1, 0) {
+        Ok(cookie) => cookie.reply().is_ok_and(|reply| reply.supported),
+        Err(_) => false,
     };
+    if !supported {
+        return false;
+    }
 
-    // TODO handle -m argument
-    handle(conn)
+    // get told when the keyboard layout changes
+    let events = xkb::EventType::NEW_KEYBOARD_NOTIFY | xkb::EventType::MAP_NOTIFY;
+    let map_parts = xkb::MapPart::KEY_TYPES | xkb::MapPart::KEY_SYMS;
+    conn.xkb_select_events(
+        xkb::ID::USE_CORE_KBD.into(),
+        0u16,
+        events,
+        map_parts,
+        map_parts,
+        &xkb::SelectEventsAux::new(),
+    )
+    .is_ok()
+}
+
+/// Fetches the keyboard mapping, from XKB if it is in use and the core protocol otherwise
+fn get_keymap(conn: &impl Connection, xkb: bool) -> Result<Keymap, ReplyError> {
+    let setup = conn.setup();
+    let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+    let count = max_keycode - min_keycode + 1;
+
+    if xkb {
+        let parts = xkb::MapPart::KEY_SYMS;
+        let reply = conn
+            .xkb_get_map(
+                xkb::ID::USE_CORE_KBD.into(),
+                parts,
+                0u16,
+                0,
+                0,
+                min_keycode,
+                count,
+                0,
+                0,
+                0,
+                0,
+                0u16,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            )?
+            .reply()?;
+
+        if let Some(syms) = reply.map.syms_rtrn {
+            let keys = syms
+                .iter()
+                .map(|key| {
+                    let width = key.width as usize;
+                    let groups = (key.group_info & 0x0f) as usize;
+                    KeySyms {
+                        groups: (0..groups)
+                            .filter_map(|group| key.syms.get(group * width).cloned())
+                            .collect(),
+                        wrap: match key.group_info & 0xc0 {
+                            0x40 => GroupWrap::Clamp,
+                            0x80 => GroupWrap::Redirect((key.group_info >> 4) & 0x3),
+                            _ => GroupWrap::Wrap,
+                        },
+                    }
+                })
+                .collect();
+
+            return Ok(Keymap::new(reply.first_key_sym, keys));
+        }
+    }
+
+    let reply = conn.get_keyboard_mapping(min_keycode, count)?.reply()?;
+    Ok(Keymap::from_core(
+        min_keycode,
+        reply.keysyms_per_keycode,
+        &reply.keysyms,
+    ))
 }
 
 fn create_window<C>(
@@ -357,31 +698,40 @@ fn create_window<C>(
     screen: &Screen,
     atoms: &AtomCollection,
     height: u16,
-    depth: u8,
-    visual_id: Visualid,
-    position: Position,
+    (depth, visual_id): (u8, Visualid),
+    monitor: &MonitorGeometry,
+    config: &Config,
 ) -> Result<(Window, u16), ReplyOrIdError>
 where
     C: Connection,
 {
-    let (screen_x, y_offset, screen_w, screen_h) = (|_conn: &C, screen: &Screen| {
-        #[cfg(feature = "multimonitor")]
-        if let Some(screen_pos) = handle_multi_monitor(_conn, screen.root) {
-            return screen_pos;
+    let (parent, area) = match config.embed {
+        Some(parent) => {
+            let geometry = conn.get_geometry(parent)?.reply()?;
+            // follow the size of the parent and take the focus whenever it gets it
+            let aux = ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::FOCUS_CHANGE);
+            conn.change_window_attributes(parent, &aux)?;
+            (parent, (0, 0, geometry.width, geometry.height))
         }
-
-        (0, 0, screen.width_in_pixels, screen.height_in_pixels)
-    })(conn, screen);
-    let screen_y = match position {
-        Position::Top => y_offset,
-        Position::Bottom => y_offset + screen_h as i16 - height as i16,
+        None => (
+            screen.root,
+            (monitor.x, monitor.y, monitor.width, monitor.height),
+        ),
     };
+    let (x, y, width) = window_geometry(config, area, height);
 
     let window = conn.generate_id()?;
     let colormap = conn.generate_id()?;
     conn.create_colormap(ColormapAlloc::NONE, colormap, screen.root, visual_id)?;
     let win_aux = CreateWindowAux::new()
-        .event_mask(EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY)
+        .event_mask(
+            EventMask::EXPOSURE
+                | EventMask::KEY_PRESS
+                | EventMask::STRUCTURE_NOTIFY
+                | EventMask::PROPERTY_CHANGE
+                | EventMask::BUTTON_PRESS,
+        )
         .background_pixel(x11rb::NONE)
         .border_pixel(screen.black_pixel)
         .colormap(colormap)
@@ -389,10 +739,10 @@ where
     conn.create_window(
         depth,
         window,
-        screen.root,
-        screen_x,
-        screen_y,
-        screen_w,
+        parent,
+        x,
+        y,
+        width,
         height,
         0,
         WindowClass::INPUT_OUTPUT,
@@ -432,68 +782,148 @@ where
     )?;
 
     conn.map_window(window)?;
-    Ok((window, screen_w))
+    Ok((window, width))
 }
 
-fn handle_keyboard(event: KeyPressEvent, menu: &mut Menu) -> XorgUiAction {
-    let previous_item = |menu: &mut Menu| {
-        menu.select_previous_item();
-        XorgUiAction::Redraw
+/// Places the window inside the area of the monitor, or of the parent when embedded
+fn window_geometry(config: &Config, area: (i16, i16, u16, u16), height: u16) -> (i16, i16, u16) {
+    let (area_x, area_y, area_w, area_h) = area;
+    let default_width = match config.position {
+        Position::Center => Dimension::Percent(50.0),
+        _ => Dimension::Percent(100.0),
     };
-    let next_item = |menu: &mut Menu| {
-        menu.select_next_item();
-        XorgUiAction::Redraw
-    };
-    let delete = |menu: &mut Menu| {
-        menu.delete_char();
-        XorgUiAction::Redraw
+    let width = config
+        .width
+        .unwrap_or(default_width)
+        .resolve(area_w)
+        .clamp(1, area_w.max(1) as i32);
+    let x = config.x.resolve(area_w);
+    let y = config.y.resolve(area_h);
+
+    let (x, y) = match config.position {
+        Position::Top => (x, y),
+        Position::Bottom => (x, area_h as i32 - height as i32 - y),
+        Position::Center => (
+            (area_w as i32 - width) / 2 + x,
+            (area_h as i32 - height as i32) / 2 + y,
+        ),
     };
-    let complete = |menu: &mut Menu| {
-        menu.complete();
+
+    (
+        (area_x as i32 + x) as i16,
+        (area_y as i32 + y) as i16,
+        width as u16,
+    )
+}
+
+fn handle_keyboard(
+    key: sys::Key,
+    menu: &mut Menu,
+    keymap: &Keymap,
+    bindings: &Bindings,
+    config: &Config,
+) -> XorgUiAction {
+    // shortcuts keep working while a non latin layout is active by falling back to the first group
+    let action = bindings
+        .get(keymap.keysym(key.keycode, key.state), key.state)
+        .or_else(|| bindings.get(keymap.keysym_in_group(key.keycode, 0), key.state));
+    if let Some(action) = action {
+        return perform(action, menu, config);
+    }
+
+    // keys that are held together with a modifier never produce text
+    if key.state & (modifier::CONTROL | modifier::ALT | modifier::SUPER) != 0 {
+        return XorgUiAction::None;
+    }
+
+    handle_text(menu, &key.text)
+}
+
+fn perform(action: Action, menu: &mut Menu, config: &Config) -> XorgUiAction {
+    let columns = Layout::new(config).columns();
+    let edit = |menu: &mut Menu, action: fn(&mut Menu)| {
+        action(menu);
         XorgUiAction::Redraw
     };
 
-    // response_type 2 => press
-    // response_type 3 => release
-    match event {
-        // Control is being held
-        KeyPressEvent {
-            response_type: 2,
-            detail,
-            state: 0x4,
-            ..
-        } => match detail {
-            XorgKeys::ENTER => XorgUiAction::Select(true),
-            _ => XorgUiAction::None,
+    match action {
+        Action::Select => XorgUiAction::Select(false),
+        Action::SelectContinue => XorgUiAction::Select(true),
+        Action::Cancel => XorgUiAction::Stop,
+        Action::Complete => edit(menu, Menu::complete),
+        Action::PreviousItem => edit(menu, Menu::select_previous_item),
+        Action::NextItem => edit(menu, Menu::select_next_item),
+        Action::ItemAbove => {
+            menu.select_item_above(columns);
+            XorgUiAction::Redraw
+        }
+        Action::ItemBelow => {
+            menu.select_item_below(columns);
+            XorgUiAction::Redraw
+        }
+        Action::PreviousPage => edit(menu, Menu::select_previous_page),
+        Action::NextPage => edit(menu, Menu::select_next_page),
+        // the cursor moves through the search term first, then through the items
+        Action::Left => match menu.cursor_at_start() {
+            true => edit(menu, Menu::select_previous_item),
+            false => edit(menu, Menu::move_cursor_left),
         },
-        // no modifiers
-        KeyPressEvent {
-            response_type: 2,
-            detail,
-            state,
-            ..
-        } => match detail {
-            XorgKeys::ENTER => XorgUiAction::Select(false),
-            XorgKeys::ESC => XorgUiAction::Stop,
-            XorgKeys::LEFT => previous_item(menu),
-            XorgKeys::RIGHT => next_item(menu),
-            XorgKeys::BACKSPACE => delete(menu),
-            XorgKeys::TAB => complete(menu),
-            key => handle_text(menu, key, state),
+        Action::Right => match menu.cursor_at_end() {
+            true => edit(menu, Menu::select_next_item),
+            false => edit(menu, Menu::move_cursor_right),
         },
-        _ => {
-            return XorgUiAction::None;
-        }
+        Action::WordLeft => edit(menu, Menu::move_cursor_word_left),
+        Action::WordRight => edit(menu, Menu::move_cursor_word_right),
+        Action::LineStart => edit(menu, Menu::move_cursor_to_start),
+        Action::LineEnd => edit(menu, Menu::move_cursor_to_end),
+        Action::DeleteBackward => edit(menu, Menu::delete_char),
+        Action::DeleteForward => edit(menu, Menu::delete_next_char),
+        Action::DeleteWord => edit(menu, Menu::delete_word),
+        Action::KillToEnd => edit(menu, Menu::kill_to_end),
+        Action::KillToStart => edit(menu, Menu::kill_to_start),
+        Action::PasteClipboard => XorgUiAction::Paste(Selection::Clipboard),
+        Action::PastePrimary => XorgUiAction::Paste(Selection::Primary),
+        Action::Nothing => XorgUiAction::None,
+    }
+}
+
+fn handle_button(event: ButtonPressEvent, menu: &mut Menu, page: &Page) -> XorgUiAction {
+    let redraw = |menu: &mut Menu, action: fn(&mut Menu)| {
+        action(menu);
+        XorgUiAction::Redraw
+    };
+    let paging = event.state & modifier::SHIFT != 0;
+
+    match event.detail {
+        // left click, with control moemenu stays open like with ctrl+Return
+        1 => match page.hit(event.event_x.into(), event.event_y.into()) {
+            Hit::Item(index) => {
+                menu.select_item(index);
+                XorgUiAction::Select(event.state & modifier::CONTROL != 0)
+            }
+            Hit::PreviousPage => redraw(menu, Menu::select_previous_page),
+            Hit::NextPage => redraw(menu, Menu::select_next_page),
+            Hit::Nothing => XorgUiAction::None,
+        },
+        // middle click
+        2 => XorgUiAction::Paste(Selection::Primary),
+        // the wheel moves the selection, or whole pages while shift is held
+        4 if paging => redraw(menu, Menu::select_previous_page),
+        5 if paging => redraw(menu, Menu::select_next_page),
+        4 => redraw(menu, Menu::select_previous_item),
+        5 => redraw(menu, Menu::select_next_item),
+        _ => XorgUiAction::None,
     }
 }
 
-fn handle_text(menu: &mut Menu, key: Keycode, mask: u16) -> XorgUiAction {
-    if let Some(ch) = sys::keycode_to_char(key, mask) {
+fn handle_text(menu: &mut Menu, text: &str) -> XorgUiAction {
+    let mut action = XorgUiAction::None;
+    for ch in text.chars().filter(|c| !c.is_control()) {
         menu.input_char(ch);
-        return XorgUiAction::Redraw;
+        action = XorgUiAction::Redraw;
     }
 
-    XorgUiAction::None
+    action
 }
 
 fn grab_keyboard(conn: &XCBConnection, screen: &Screen) -> Result<(), KeyboardGrabError> {
@@ -515,22 +945,51 @@ fn grab_keyboard(conn: &XCBConnection, screen: &Screen) -> Result<(), KeyboardGr
 }
 
 impl XorgUserInterface {
-    pub fn new(config: Config) -> Result<XorgUserInterface, Box<dyn std::error::Error>> {
-        let (conn, screen_num) = XCBConnection::connect(None)?;
+    pub fn new(
+        xorg: XorgConnection,
+        config: Config,
+    ) -> Result<XorgUserInterface, Box<dyn std::error::Error>> {
+        // the connection has to be dropped before the display that owns it
+        let display = xorg.display;
+        let conn = xorg.connection;
+        let resources = xorg.resources;
+        let screen_num = display.default_screen();
         let screen = &conn.setup().roots[screen_num];
         let atoms = AtomCollection::new(&conn)?.reply()?;
-        let height = config.height;
+
+        let mut config = config;
+        let monitor = pick_monitor(&conn, screen, &config.monitor);
+        let scale = match config.scale {
+            Some(scale) => scale,
+            None => detect_scale(&resources, &monitor),
+        };
+        config.apply_scale(scale);
+
+        // one line for the search term and one for every row of items, framed by border and padding
+        let height = config
+            .height
+            .saturating_mul(Layout::new(&config).lines())
+            .saturating_add(draw::inset(&config).saturating_mul(2));
         let (depth, visualid) = choose_visual(&conn, screen_num)?;
 
         // Check if a composite manager is running. In a real application, we should also react to a
         // composite manager starting/stopping at runtime.
-        let transparency = composite_manager_running(&conn, screen_num)?;
+        // Alpha and rounded corners also need the ARGB visual.
+        let transparency = composite_manager_running(&conn, screen_num)? && depth == 32;
+        if !transparency {
+            config.colors.flatten();
+        }
+
+        let xkb = use_xkb(&conn);
+        let keymap = get_keymap(&conn, xkb)?;
 
-        // grab keyboard
-        let grab_result = grab_keyboard(&conn, &screen);
+        // grab keyboard, an embedded window only takes the focus
+        if config.embed.is_none() {
+            let grab_result = grab_keyboard(&conn, &screen);
 
-        if grab_result.is_err() {
-            return Err(Box::from(grab_result.err().unwrap()));
+            if grab_result.is_err() {
+                return Err(Box::from(grab_result.err().unwrap()));
+            }
         }
 
         let (window, width) = create_window(
@@ -538,11 +997,23 @@ impl XorgUserInterface {
             &screen,
             &atoms,
             height,
-            depth,
-            visualid,
-            config.position.clone(),
+            (depth, visualid),
+            &monitor,
+            &config,
         )?;
 
+        if config.embed.is_some() {
+            take_focus(&conn, window)?;
+        }
+
+        // the input method might need to see more events than moemenu does
+        let im_events = display.create_input_context(window);
+        if im_events != 0 {
+            let events = conn.get_window_attributes(window)?.reply()?.your_event_mask | im_events;
+            let aux = ChangeWindowAttributesAux::new().event_mask(events);
+            conn.change_window_attributes(window, &aux)?;
+        }
+
         // Here comes all the interaction between cairo and x11rb:
         let mut visual = find_xcb_visualtype(&conn, visualid).unwrap();
         // SAFETY: cairo-rs just passes the pointer to C code and C code uses the xcb_connection_t, so
@@ -577,21 +1048,231 @@ impl XorgUserInterface {
             width,
             height,
             transparency,
+            bindings: Bindings::new(&config.keybindings),
+            keymap,
+            xkb,
+            paste: None,
             config,
+            display,
         })
     }
 }
 
+fn take_focus(conn: &XCBConnection, window: Window) -> Result<(), Box<dyn Error>> {
+    let wait_time = time::Duration::from_millis(10);
+    for _ in 1..=100 {
+        if conn.get_input_focus()?.reply()?.focus == window {
+            return Ok(());
+        }
+
+        // fails until the window is mapped
+        conn.set_input_focus(InputFocus::PARENT, window, x11rb::CURRENT_TIME)?;
+        thread::sleep(wait_time);
+    }
+
+    Err(Box::from(KeyboardGrabError::new(
+        "failed to take the focus",
+    )))
+}
+
+impl XorgUserInterface {
+    /// Keeps the embedded window as wide as its parent
+    fn follow_parent(&mut self, event: ConfigureNotifyEvent) -> Result<(), Box<dyn Error>> {
+        let area = (0, 0, event.width, event.height);
+        let (x, y, width) = window_geometry(&self.config, area, self.height);
+        let aux = ConfigureWindowAux::new()
+            .x(x as i32)
+            .y(y as i32)
+            .width(width as u32);
+        self.connection.configure_window(self.window, &aux)?;
+        self.surface.set_size(width.into(), self.height.into())?;
+        self.width = width;
+
+        Ok(())
+    }
+
+    /// Carries out what a key or button asked for, returns the result once moemenu is done
+    fn apply(
+        &mut self,
+        action: XorgUiAction,
+        menu: &Menu,
+        need_redraw: &mut bool,
+    ) -> Result<Option<(String, bool)>, Box<dyn Error>> {
+        match action {
+            XorgUiAction::Stop => {
+                return Err(Box::from(NoSelectionError::new()));
+            }
+            XorgUiAction::Select(should_continue) => {
+                return match menu.get_selected_item() {
+                    Some(s) => Ok(Some((s, should_continue))),
+                    None => Ok(Some((menu.get_search_term(), should_continue))),
+                }
+            }
+            XorgUiAction::Redraw => {
+                *need_redraw = true;
+            }
+            XorgUiAction::Paste(selection) => self.request_paste(selection)?,
+            XorgUiAction::None => {}
+        };
+
+        Ok(None)
+    }
+
+    /// Asks the owner of the selection to convert it to text, the answer is a SelectionNotify
+    fn request_paste(&mut self, selection: Selection) -> Result<(), Box<dyn Error>> {
+        let selection = match selection {
+            Selection::Clipboard => self.atoms.CLIPBOARD,
+            Selection::Primary => AtomEnum::PRIMARY.into(),
+        };
+        let target = self.atoms.UTF8_STRING;
+        self.connection.convert_selection(
+            self.window,
+            selection,
+            target,
+            self.atoms.MOEMENU_PASTE,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.paste = Some(Paste {
+            target,
+            incremental: false,
+            data: Vec::new(),
+        });
+
+        Ok(())
+    }
+
+    /// Returns true if text was pasted
+    fn receive_paste(
+        &mut self,
+        event: SelectionNotifyEvent,
+        menu: &mut Menu,
+    ) -> Result<bool, Box<dyn Error>> {
+        let target = match &self.paste {
+            Some(paste) if event.requestor == self.window => paste.target,
+            _ => return Ok(false),
+        };
+
+        if event.property == x11rb::NONE {
+            // old clients only know latin-1
+            if target == self.atoms.UTF8_STRING {
+                self.connection.convert_selection(
+                    self.window,
+                    event.selection,
+                    AtomEnum::STRING.into(),
+                    self.atoms.MOEMENU_PASTE,
+                    x11rb::CURRENT_TIME,
+                )?;
+                self.paste = Some(Paste {
+                    target: AtomEnum::STRING.into(),
+                    incremental: false,
+                    data: Vec::new(),
+                });
+            } else {
+                self.paste = None;
+            }
+            return Ok(false);
+        }
+
+        // deleting the property tells the owner to go on with an incremental transfer
+        let reply = self
+            .connection
+            .get_property(
+                true,
+                self.window,
+                event.property,
+                AtomEnum::ANY,
+                0,
+                u32::MAX,
+            )?
+            .reply()?;
+        if reply.type_ == self.atoms.INCR {
+            if let Some(paste) = &mut self.paste {
+                paste.incremental = true;
+            }
+            return Ok(false);
+        }
+
+        if let Some(paste) = &mut self.paste {
+            paste.data = reply.value;
+        }
+        Ok(self.finish_paste(menu))
+    }
+
+    /// Collects the chunks of an incremental transfer, returns true once it is complete
+    fn receive_paste_chunk(
+        &mut self,
+        event: PropertyNotifyEvent,
+        menu: &mut Menu,
+    ) -> Result<bool, Box<dyn Error>> {
+        match &self.paste {
+            Some(paste) if paste.incremental => {}
+            _ => return Ok(false),
+        }
+        if event.window != self.window
+            || event.atom != self.atoms.MOEMENU_PASTE
+            || event.state != Property::NEW_VALUE
+        {
+            return Ok(false);
+        }
+
+        let reply = self
+            .connection
+            .get_property(true, self.window, event.atom, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?;
+        // an empty chunk marks the end
+        if reply.value.is_empty() {
+            return Ok(self.finish_paste(menu));
+        }
+
+        if let Some(paste) = &mut self.paste {
+            paste.data.extend_from_slice(&reply.value);
+        }
+        Ok(false)
+    }
+
+    fn finish_paste(&mut self, menu: &mut Menu) -> bool {
+        let paste = match self.paste.take() {
+            Some(paste) => paste,
+            None => return false,
+        };
+
+        let text = match paste.target == self.atoms.UTF8_STRING {
+            true => String::from_utf8_lossy(&paste.data).into_owned(),
+            false => paste.data.iter().map(|byte| *byte as char).collect(),
+        };
+        menu.paste(&text);
+        true
+    }
+}
+
 impl UserInterface for XorgUserInterface {
     fn run(&mut self, menu: &mut Menu) -> Result<(String, bool), Box<dyn std::error::Error>> {
         let cr = cairo::Context::new(&self.surface);
         loop {
             self.connection.flush()?;
-            let event = self.connection.wait_for_event()?;
-            let mut event_option = Some(event);
+            let mut raw_event = Some(self.connection.wait_for_raw_event()?);
             let mut need_redraw = false;
-            while let Some(event) = event_option {
-                match event {
+            while let Some(raw) = raw_event {
+                raw_event = self.connection.poll_for_raw_event()?;
+
+                // the input method sees every event first and hands back the key presses
+                let filtered = self.display.filter_event(&raw);
+                while let Some(key) = self.display.next_key() {
+                    let action =
+                        handle_keyboard(key, menu, &self.keymap, &self.bindings, &self.config);
+                    if let Some(result) = self.apply(action, menu, &mut need_redraw)? {
+                        return Ok(result);
+                    }
+                }
+                if let Some((preedit, cursor)) = self.display.preedit() {
+                    menu.set_preedit(preedit, cursor);
+                    need_redraw = true;
+                }
+                if filtered {
+                    continue;
+                }
+
+                match self.connection.parse_event(&raw)? {
                     Event::Expose(_) => {
                         need_redraw = true;
                     }
@@ -605,46 +1286,75 @@ impl UserInterface for XorgUserInterface {
                             return Err(Box::from(NoSelectionError::new()));
                         }
                     }
-                    Event::KeyPress(event) | Event::KeyRelease(event) => {
-                        match handle_keyboard(event, menu) {
-                            XorgUiAction::Stop => {
-                                return Err(Box::from(NoSelectionError::new()));
-                            }
-                            XorgUiAction::Select(should_continue) => {
-                                return match menu.get_selected_item() {
-                                    Some(s) => Ok((s, should_continue)),
-                                    None => Ok((menu.get_search_term(), should_continue)),
-                                }
-                            }
-                            XorgUiAction::Redraw => {
-                                need_redraw = true;
-                            }
-                            XorgUiAction::None => {}
-                        };
+                    Event::ConfigureNotify(event) if Some(event.window) == self.config.embed => {
+                        self.follow_parent(event)?;
+                        need_redraw = true;
+                    }
+                    Event::FocusIn(event) if Some(event.event) == self.config.embed => {
+                        self.connection.set_input_focus(
+                            InputFocus::PARENT,
+                            self.window,
+                            x11rb::CURRENT_TIME,
+                        )?;
+                    }
+                    Event::DestroyNotify(event) if Some(event.window) == self.config.embed => {
+                        eprintln!("Parent window was destroyed");
+                        return Err(Box::from(NoSelectionError::new()));
+                    }
+                    // structure and focus changes that need no reaction
+                    Event::ConfigureNotify(_)
+                    | Event::FocusIn(_)
+                    | Event::FocusOut(_)
+                    | Event::MapNotify(_)
+                    | Event::UnmapNotify(_)
+                    | Event::ReparentNotify(_)
+                    | Event::GravityNotify(_)
+                    | Event::CirculateNotify(_)
+                    | Event::DestroyNotify(_) => {}
+                    Event::SelectionNotify(event) => {
+                        need_redraw |= self.receive_paste(event, menu)?;
+                    }
+                    Event::PropertyNotify(event) => {
+                        need_redraw |= self.receive_paste_chunk(event, menu)?;
+                    }
+                    Event::ButtonPress(mut event) => {
+                        // the page is laid out inside the border and padding
+                        let inset = draw::inset(&self.config) as i16;
+                        event.event_x -= inset;
+                        event.event_y -= inset;
+                        let page = draw::current_page(&cr, self.width as f64, &self.config, menu);
+                        let action = handle_button(event, menu, &page);
+                        if let Some(result) = self.apply(action, menu, &mut need_redraw)? {
+                            return Ok(result);
+                        }
+                    }
+                    // already handled by the input method above
+                    Event::KeyPress(_) | Event::KeyRelease(_) => {}
+                    // the keyboard layout changed
+                    Event::MappingNotify(MappingNotifyEvent {
+                        request: Mapping::KEYBOARD,
+                        ..
+                    })
+                    | Event::XkbMapNotify(_)
+                    | Event::XkbNewKeyboardNotify(_) => {
+                        let setup = self.connection.setup();
+                        let count = setup.max_keycode - setup.min_keycode + 1;
+                        self.display
+                            .refresh_keyboard_mapping(setup.min_keycode, count);
+                        self.keymap = get_keymap(&self.connection, self.xkb)?;
                     }
                     Event::Error(_) => eprintln!("Got an unexpected error"),
                     e => eprintln!("Got an unknown event: {:?}", e),
                 }
-                event_option = self.connection.poll_for_event()?;
             }
 
             // ensure selection does not go of screen
-            let items = menu.get_items();
-            let last_item = draw::find_last_item_that_fits(
-                &cr,
-                self.width as f64,
-                menu.get_shift() as usize,
-                &self.config,
-                items,
-            );
-            let first_item = draw::find_first_item_that_fits(
-                &cr,
-                self.width as f64,
-                menu.get_shift() as usize,
-                &self.config,
-                items,
-            );
-            menu.update_page(first_item, last_item);
+            let page = draw::current_page(&cr, self.width as f64, &self.config, menu);
+            if menu.update_page(page.previous_start, page.last_item(), page.size) {
+                // remember the bounds of the new page
+                let page = draw::current_page(&cr, self.width as f64, &self.config, menu);
+                menu.update_page(page.previous_start, page.last_item(), page.size);
+            }
 
             if need_redraw {
                 do_draw(